@@ -0,0 +1,810 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ast::*;
+use crate::class::CharClass;
+use crate::visit::{Visitable, Visitor};
+
+/// Literal string every match has to start with, which is empty if there's
+/// none. Only the leading literals of the top-level concatenation count.
+pub fn literal_prefix(ast: &Ast) -> String {
+    match ast {
+        Ast::Literal(literal) => literal.value().to_string(),
+        Ast::Literals(literals) => literals.value().to_string(),
+        Ast::Concatenation(concat) => {
+            let mut prefix = String::new();
+            for item in concat.items() {
+                match item {
+                    Ast::Literal(literal) => prefix.push(literal.value()),
+                    Ast::Literals(literals) => prefix.push_str(literals.value()),
+                    _ => break,
+                }
+            }
+            prefix
+        }
+        _ => String::new(),
+    }
+}
+
+/// Names of the named groups in `ast`, in the order they are opened.
+pub fn group_names(ast: &Ast) -> Vec<&str> {
+    let mut names = Vec::new();
+    push_group_names(ast, &mut names);
+    names
+}
+
+fn push_group_names<'a>(ast: &'a Ast, names: &mut Vec<&'a str>) {
+    match ast {
+        Ast::Concatenation(concat) => {
+            for item in concat.items() {
+                push_group_names(item, names);
+            }
+        }
+        Ast::Alternative(alt) => {
+            for item in alt.items() {
+                push_group_names(item, names);
+            }
+        }
+        Ast::Group(group) => {
+            if let GroupKind::Named(name) = group.kind() {
+                names.push(name);
+            }
+            push_group_names(group.inner(), names);
+        }
+        Ast::Repetition(rep) => push_group_names(rep.inner(), names),
+        Ast::Look(look) => push_group_names(look.inner(), names),
+        Ast::Atomic(inner) => push_group_names(inner, names),
+        _ => {}
+    }
+}
+
+/// Number of nodes in `ast`, counting every bracket expression as one too.
+pub fn node_count(ast: &Ast) -> usize {
+    ast.accept(&mut NodeCount)
+}
+
+struct NodeCount;
+
+impl Visitor<usize> for NodeCount {
+    fn visit(&mut self, node: &Ast) -> usize {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, _: &Literal) -> usize {
+        1
+    }
+
+    fn visit_literals(&mut self, _: &Literals) -> usize {
+        1
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> usize {
+        1
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> usize {
+        1
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> usize {
+        1
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> usize {
+        1
+    }
+
+    fn visit_bracket(&mut self, node: &Bracket) -> usize {
+        1 + node.exprs().len()
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> usize {
+        1 + node.items().iter().map(|x| self.visit(x)).sum::<usize>()
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> usize {
+        1 + node.items().iter().map(|x| self.visit(x)).sum::<usize>()
+    }
+
+    fn visit_group(&mut self, node: &Group) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_look(&mut self, node: &LookAround) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> usize {
+        1 + self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> usize {
+        1
+    }
+}
+
+/// Deepest nesting of groups, repetitions and alternations in `ast`, which is
+/// 0 for patterns without any.
+pub fn depth(ast: &Ast) -> usize {
+    ast.accept(&mut Depth)
+}
+
+struct Depth;
+
+impl Visitor<usize> for Depth {
+    fn visit(&mut self, node: &Ast) -> usize {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, _: &Literal) -> usize {
+        0
+    }
+
+    fn visit_literals(&mut self, _: &Literals) -> usize {
+        0
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> usize {
+        0
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> usize {
+        0
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> usize {
+        0
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> usize {
+        0
+    }
+
+    fn visit_bracket(&mut self, _: &Bracket) -> usize {
+        0
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> usize {
+        node.items()
+            .iter()
+            .map(|x| self.visit(x))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> usize {
+        1 + node
+            .items()
+            .iter()
+            .map(|x| self.visit(x))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn visit_group(&mut self, node: &Group) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_look(&mut self, node: &LookAround) -> usize {
+        1 + self.visit(node.inner())
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> usize {
+        1 + self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> usize {
+        0
+    }
+}
+
+/// Minimum and maximum number of characters a match of `ast` consumes, where
+/// the maximum is `None` if it's unbounded or too large for a `usize`.
+///
+/// A descending range like `{3,2}`, which can't be compiled, counts as `{0}`.
+pub fn match_length_bounds(ast: &Ast) -> (usize, Option<usize>) {
+    ast.accept(&mut LengthBounds)
+}
+
+struct LengthBounds;
+
+impl Visitor<(usize, Option<usize>)> for LengthBounds {
+    fn visit(&mut self, node: &Ast) -> (usize, Option<usize>) {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, _: &Literal) -> (usize, Option<usize>) {
+        (1, Some(1))
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> (usize, Option<usize>) {
+        let n = node.value().chars().count();
+        (n, Some(n))
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> (usize, Option<usize>) {
+        (1, Some(1))
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> (usize, Option<usize>) {
+        (1, Some(1))
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+
+    fn visit_bracket(&mut self, _: &Bracket) -> (usize, Option<usize>) {
+        (1, Some(1))
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> (usize, Option<usize>) {
+        node.items()
+            .iter()
+            .map(|x| self.visit(x))
+            .fold((0, Some(0)), |(min, max), (a, b)| {
+                let max = max.zip(b).and_then(|(max, b)| max.checked_add(b));
+                (min.saturating_add(a), max)
+            })
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> (usize, Option<usize>) {
+        let mut bounds = node.items().iter().map(|x| self.visit(x));
+        let first = bounds.next().unwrap_or((0, Some(0)));
+        bounds.fold(first, |(min, max), (a, b)| {
+            (min.min(a), max.zip(b).map(|(max, b)| max.max(b)))
+        })
+    }
+
+    fn visit_group(&mut self, node: &Group) -> (usize, Option<usize>) {
+        self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> (usize, Option<usize>) {
+        use Quantifier::*;
+        let (min, max) = self.visit(node.inner());
+        // Repeating something that only matches empty never gets longer.
+        let unbounded = if max == Some(0) { Some(0) } else { None };
+        let times = |n: u8| {
+            let max = max.and_then(|max| max.checked_mul(usize::from(n)));
+            (min.saturating_mul(usize::from(n)), max)
+        };
+        match node.quantifier() {
+            ZeroOrOne => (0, max),
+            ZeroOrMore => (0, unbounded),
+            OneOrMore => (min, unbounded),
+            Exact(n) => times(n),
+            Minimum(n) => (times(n).0, unbounded),
+            Range(n, m) if n > m => (0, Some(0)),
+            Range(n, m) => (times(n).0, times(m).1),
+        }
+    }
+
+    fn visit_look(&mut self, _: &LookAround) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> (usize, Option<usize>) {
+        self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+/// Whether `ast` can match the empty string, like `a*` and `(a|)` but not `a+`.
+/// Assertions count as matching it, wherever they hold.
+pub fn is_nullable(ast: &Ast) -> bool {
+    ast.accept(&mut Nullable)
+}
+
+struct Nullable;
+
+impl Visitor<bool> for Nullable {
+    fn visit(&mut self, node: &Ast) -> bool {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, _: &Literal) -> bool {
+        false
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> bool {
+        node.value().is_empty()
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> bool {
+        false
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> bool {
+        true
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> bool {
+        false
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> bool {
+        true
+    }
+
+    fn visit_bracket(&mut self, _: &Bracket) -> bool {
+        false
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> bool {
+        node.items().iter().all(|x| self.visit(x))
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> bool {
+        node.items().iter().any(|x| self.visit(x))
+    }
+
+    fn visit_group(&mut self, node: &Group) -> bool {
+        self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> bool {
+        use Quantifier::*;
+        match node.quantifier() {
+            ZeroOrOne | ZeroOrMore | Exact(0) | Minimum(0) | Range(0, _) => true,
+            OneOrMore | Exact(_) | Minimum(_) | Range(..) => self.visit(node.inner()),
+        }
+    }
+
+    fn visit_look(&mut self, _: &LookAround) -> bool {
+        true
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> bool {
+        self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> bool {
+        // The group it refers to may have matched the empty string.
+        true
+    }
+}
+
+/// Characters a match of `ast` can start with, and whether it can also be
+/// empty, in which case it may start with anything that follows it. Inline
+/// flags are taken into account, starting with all of them cleared.
+pub fn first_set(ast: &Ast) -> (CharClass, bool) {
+    ast.accept(&mut FirstSet {
+        case_insensitive: false,
+        dot_matches_new_line: false,
+        unicode: false,
+        last: false,
+    })
+}
+
+/// Characters a non-empty match of `ast` can end with, like `first_set` from
+/// the other end.
+pub fn last_set(ast: &Ast) -> CharClass {
+    let (class, _) = ast.accept(&mut FirstSet {
+        case_insensitive: false,
+        dot_matches_new_line: false,
+        unicode: false,
+        last: true,
+    });
+    class
+}
+
+#[derive(Copy, Clone)]
+struct FirstSet {
+    case_insensitive: bool,
+    dot_matches_new_line: bool,
+    unicode: bool,
+    /// Collects the characters matches end with instead.
+    last: bool,
+}
+
+impl FirstSet {
+    fn apply(&mut self, flags: &Flags) {
+        if let Some(value) = flags.case_insensitive() {
+            self.case_insensitive = value;
+        }
+        if let Some(value) = flags.dot_matches_new_line() {
+            self.dot_matches_new_line = value;
+        }
+        if let Some(value) = flags.unicode() {
+            self.unicode = value;
+        }
+    }
+}
+
+impl Visitor<(CharClass, bool)> for FirstSet {
+    fn visit(&mut self, node: &Ast) -> (CharClass, bool) {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &Literal) -> (CharClass, bool) {
+        let mut class = CharClass::from_char(node.value());
+        if self.case_insensitive {
+            class.case_fold();
+        }
+        (class, false)
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> (CharClass, bool) {
+        let mut chars = node.value().chars();
+        let c = if self.last {
+            chars.next_back()
+        } else {
+            chars.next()
+        };
+        match c {
+            Some(c) => self.visit_literal(&Literal::new(c)),
+            None => (CharClass::new(), true),
+        }
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> (CharClass, bool) {
+        if self.dot_matches_new_line {
+            (CharClass::from_range('\0', char::MAX), false)
+        } else {
+            (CharClass::wildcard(), false)
+        }
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> (CharClass, bool) {
+        (CharClass::new(), true)
+    }
+
+    fn visit_perl_class(&mut self, node: &PerlClass) -> (CharClass, bool) {
+        (CharClass::from_perl(node, self.unicode), false)
+    }
+
+    fn visit_flags(&mut self, node: &Flags) -> (CharClass, bool) {
+        self.apply(node);
+        (CharClass::new(), true)
+    }
+
+    fn visit_bracket(&mut self, node: &Bracket) -> (CharClass, bool) {
+        if node.has_unicode_property() {
+            return (CharClass::from_range('\0', char::MAX), false);
+        }
+        let mut class = CharClass::from_exprs(node.exprs(), self.unicode);
+        if self.case_insensitive {
+            class.case_fold();
+        }
+        if node.negated() {
+            class.negate();
+        }
+        (class, false)
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> (CharClass, bool) {
+        let mut set = CharClass::new();
+        let mut empty = true;
+        // Items past the first non-empty one still get visited for their flags,
+        // so the last ones are found going forwards too.
+        for item in node.items() {
+            let (class, can_be_empty) = self.visit(item);
+            if self.last {
+                if !can_be_empty {
+                    set = CharClass::new();
+                }
+                set.union(&class);
+                empty &= can_be_empty;
+            } else if empty {
+                set.union(&class);
+                empty = can_be_empty;
+            }
+        }
+        (set, empty)
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> (CharClass, bool) {
+        let mut first = CharClass::new();
+        let mut empty = false;
+        for item in node.items() {
+            let (class, can_be_empty) = self.visit(item);
+            first.union(&class);
+            empty |= can_be_empty;
+        }
+        (first, empty)
+    }
+
+    fn visit_group(&mut self, node: &Group) -> (CharClass, bool) {
+        let outer = *self;
+        if let GroupKind::NonCapturing(flags) = node.kind() {
+            self.apply(flags);
+        }
+        let first = self.visit(node.inner());
+        *self = outer;
+        first
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> (CharClass, bool) {
+        use Quantifier::*;
+        let (class, can_be_empty) = self.visit(node.inner());
+        let optional = match node.quantifier() {
+            // Never repeated at all, so nothing of it is ever consumed.
+            Exact(0) | Range(0, 0) => return (CharClass::new(), true),
+            ZeroOrOne | ZeroOrMore => true,
+            OneOrMore => false,
+            Exact(n) | Minimum(n) | Range(n, _) => n == 0,
+        };
+        (class, can_be_empty || optional)
+    }
+
+    fn visit_look(&mut self, _: &LookAround) -> (CharClass, bool) {
+        (CharClass::new(), true)
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> (CharClass, bool) {
+        let outer = *self;
+        let first = self.visit(node);
+        *self = outer;
+        first
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> (CharClass, bool) {
+        (CharClass::from_range('\0', char::MAX), true)
+    }
+}
+
+/// Sorted characters written literally in `ast`, either on their own or in
+/// brackets, where ranges contribute their endpoints.
+pub fn literals(ast: &Ast) -> Vec<char> {
+    let mut chars = ast.accept(&mut LiteralChars);
+    chars.sort_unstable();
+    chars.dedup();
+    chars
+}
+
+struct LiteralChars;
+
+impl Visitor<Vec<char>> for LiteralChars {
+    fn visit(&mut self, node: &Ast) -> Vec<char> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &Literal) -> Vec<char> {
+        vec![node.value()]
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> Vec<char> {
+        node.value().chars().collect()
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> Vec<char> {
+        Vec::new()
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> Vec<char> {
+        Vec::new()
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> Vec<char> {
+        Vec::new()
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> Vec<char> {
+        Vec::new()
+    }
+
+    fn visit_bracket(&mut self, node: &Bracket) -> Vec<char> {
+        let mut chars = Vec::new();
+        for expr in node.exprs() {
+            match *expr {
+                BracketExpr::Char(c) => chars.push(c),
+                BracketExpr::Range(a, b) => chars.extend(&[a, b]),
+                BracketExpr::Class(..) | BracketExpr::UnicodeProperty { .. } => {}
+            }
+        }
+        chars
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> Vec<char> {
+        node.items().iter().flat_map(|x| self.visit(x)).collect()
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> Vec<char> {
+        node.items().iter().flat_map(|x| self.visit(x)).collect()
+    }
+
+    fn visit_group(&mut self, node: &Group) -> Vec<char> {
+        self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> Vec<char> {
+        self.visit(node.inner())
+    }
+
+    fn visit_look(&mut self, node: &LookAround) -> Vec<char> {
+        self.visit(node.inner())
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> Vec<char> {
+        self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, _: &Backreference) -> Vec<char> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    fn prefix(pattern: &str) -> String {
+        literal_prefix(&parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn prefix_of_concatenation() {
+        assert_eq!(prefix("abc.*"), "abc");
+        assert_eq!(prefix(".*abc"), "");
+        assert_eq!(prefix("a"), "a");
+        assert_eq!(prefix("ab+"), "a");
+        assert_eq!(prefix("ab|ac"), "");
+        assert_eq!(prefix("a(?i)b"), "a");
+        assert_eq!(prefix("(?i)ab"), "");
+    }
+
+    #[test]
+    fn count_nodes() {
+        let count = |pattern| node_count(&parse(pattern).unwrap());
+        assert_eq!(count("a"), 1);
+        assert_eq!(count("a|b"), 3);
+        assert_eq!(count("(ab)+"), 3);
+        assert_eq!(count("(a.)+"), 5);
+        assert_eq!(count("[a-c[:digit:]x]"), 4);
+        assert_eq!(count(r"^\d.(?i)$"), 6);
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let depth = |pattern| depth(&parse(pattern).unwrap());
+        assert_eq!(depth("a"), 0);
+        assert_eq!(depth("abc[de]"), 0);
+        assert_eq!(depth("a|b"), 1);
+        assert_eq!(depth("((a))"), 2);
+        assert_eq!(depth("a(b)c((d))"), 2);
+        assert_eq!(depth("(a*|b)+"), 4);
+        let nested = format!("{}a{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(depth(&nested), 50);
+    }
+
+    #[test]
+    fn length_bounds() {
+        let bounds = |pattern| match_length_bounds(&parse(pattern).unwrap());
+        assert_eq!(bounds("a{2,4}"), (2, Some(4)));
+        assert_eq!(bounds("ab"), (2, Some(2)));
+        assert_eq!(bounds("a*b"), (1, None));
+        assert_eq!(bounds("^a{3}$"), (3, Some(3)));
+        assert_eq!(bounds("(ab|c)?d+"), (1, None));
+        assert_eq!(bounds("a|bcd|[ef]."), (1, Some(3)));
+        assert_eq!(bounds("(x{2,})"), (2, None));
+        assert_eq!(bounds("(^)+"), (0, Some(0)));
+        assert_eq!(bounds("a{3,2}"), (0, Some(0)));
+        assert_eq!(bounds("xa{3,2}"), (1, Some(1)));
+        let huge = format!("{}a{}", "(?:".repeat(10), "){255}".repeat(10));
+        assert_eq!(bounds(&huge), (usize::MAX, None));
+        let huge = format!("{}a{}b*", "(?:".repeat(10), "){0,255}".repeat(10));
+        assert_eq!(bounds(&huge), (0, None));
+    }
+
+    #[test]
+    fn nullable() {
+        let nullable = |pattern| is_nullable(&parse(pattern).unwrap());
+        for pattern in [
+            "a*",
+            "a?",
+            "(a|b?)",
+            "a{0,5}",
+            "a{0}",
+            "(?:a*b?)+",
+            "^$",
+            "(?=a)",
+        ] {
+            assert!(nullable(pattern), "{}", pattern);
+        }
+        // `(a|)`, which doesn't parse, with an empty concatenation for the
+        // empty branch like `simplify` leaves.
+        let empty = Ast::Concatenation(Concatenation::new(Vec::new()));
+        let alt = Alternative::new(vec![Ast::Literal(Literal::new('a')), empty]);
+        assert!(is_nullable(&Ast::Group(Group::new(Ast::Alternative(alt)))));
+        for pattern in ["a", "a+", "a{1,}", "a*b", "[ab]{2,3}", "a|.", r"^\d$"] {
+            assert!(!nullable(pattern), "{}", pattern);
+        }
+    }
+
+    fn first(pattern: &str) -> (CharClass, bool) {
+        first_set(&parse(pattern).unwrap())
+    }
+
+    fn chars(chars: &str) -> CharClass {
+        let mut class = CharClass::new();
+        chars.chars().for_each(|c| class.push(c, c));
+        class
+    }
+
+    #[test]
+    fn first_set_alternation() {
+        assert_eq!(first("(abc|def)"), (chars("ad"), false));
+        assert_eq!(first("a|b?|c"), (chars("abc"), true));
+        assert_eq!(first("(?:x|y)z"), (chars("xy"), false));
+    }
+
+    #[test]
+    fn first_set_optional_prefix() {
+        assert_eq!(first("a*b"), (chars("ab"), false));
+        assert_eq!(first("a?b{0,2}c"), (chars("abc"), false));
+        assert_eq!(first("^a{0}b+"), (chars("b"), false));
+        assert_eq!(first("(?:ab){0,0}c"), (chars("c"), false));
+        assert_eq!(first("a{0}"), (chars(""), true));
+        assert_eq!(first("a*"), (chars("a"), true));
+        assert_eq!(first("(?i)k"), (chars("Kk"), false));
+        assert_eq!(first("(?i:a)?b"), (chars("Aab"), false));
+    }
+
+    #[test]
+    fn first_set_bracket() {
+        assert_eq!(first("[a-c]x"), (CharClass::from_range('a', 'c'), false));
+        let (class, empty) = first("[^a]?b");
+        assert!(!empty && class.contains('b') && class.contains('z'));
+        assert!(!class.contains('a'));
+    }
+
+    fn last(pattern: &str) -> CharClass {
+        last_set(&parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn last_set_alternation() {
+        assert_eq!(last("(abc|xyz)d?"), chars("cdz"));
+        assert_eq!(last("a|bc|d*"), chars("acd"));
+        assert_eq!(last("(?:x|y)z"), chars("z"));
+    }
+
+    #[test]
+    fn last_set_optional_suffix() {
+        assert_eq!(last("ab?c*"), chars("abc"));
+        assert_eq!(last("ab{0,2}$"), chars("ab"));
+        assert_eq!(last("ab{0}"), chars("a"));
+        assert_eq!(last("a(?:bc){0,0}$"), chars("a"));
+        assert_eq!(last("a(?i)k"), chars("Kk"));
+        assert_eq!(last("(?i:a)b?"), chars("Aab"));
+        assert_eq!(last("x[a-c]"), CharClass::from_range('a', 'c'));
+    }
+
+    #[test]
+    fn literal_chars() {
+        let literals = |pattern| literals(&parse(pattern).unwrap());
+        assert_eq!(literals("[a-c]x"), vec!['a', 'c', 'x']);
+        assert_eq!(literals(r"(b|a)+\d.[^[:alpha:]z]b"), vec!['a', 'b', 'z']);
+        assert_eq!(literals("^.$"), vec![]);
+    }
+
+    #[test]
+    fn named_groups() {
+        let ast = parse("(?P<a>x)(?P<b>y)").unwrap();
+        assert_eq!(group_names(&ast), vec!["a", "b"]);
+        let names = |pattern| group_names(&parse(pattern).unwrap()).join(",");
+        assert_eq!(
+            names("(?P<outer>(?<inner>x)|(y))*(?P<last>z)"),
+            "outer,inner,last"
+        );
+        assert_eq!(names("(x)(?:y)"), "");
+    }
+}
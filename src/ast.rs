@@ -1,64 +1,218 @@
-use std::str::FromStr;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+use core::str::FromStr;
 
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, digit1, none_of, one_of},
-    combinator::{map, opt},
-    multi::{many1, separated_nonempty_list},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    bytes::complete::{tag, take_while1},
+    character::complete::{anychar, char, digit1, none_of, one_of},
+    combinator::{map, opt, verify},
+    error::ErrorKind,
+    multi::{fold_many0, fold_many1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
+use crate::class::CharClass;
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Ast {
     Literal(Literal),
+    Literals(Literals),
     Wildcard(Wildcard),
+    Anchor(Anchor),
+    PerlClass(PerlClass),
+    Flags(Flags),
     Bracket(Bracket),
     Concatenation(Concatenation),
     Alternative(Alternative),
     Group(Group),
     Repetition(Repetition),
+    Look(LookAround),
+    /// (?>...), which never backtracks into the inner node once it matched.
+    Atomic(Box<Ast>),
+    Backreference(Backreference),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Literal {
     value: char,
 }
 
 impl Literal {
+    pub fn new(value: char) -> Literal {
+        Literal { value }
+    }
+
     pub fn value(&self) -> char {
         self.value
     }
+
+    pub fn set_value(&mut self, value: char) {
+        self.value = value;
+    }
 }
 
+/// Run of literal characters matched one after another, which the parser
+/// emits for two or more adjacent literals.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Literals {
+    value: String,
+}
+
+impl Literals {
+    pub fn new(value: String) -> Literals {
+        Literals { value }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn set_value(&mut self, value: String) {
+        self.value = value;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Wildcard;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Anchor {
+    /// ^
+    Start,
+    /// $
+    End,
+    /// \A
+    StartOfText,
+    /// \z
+    EndOfText,
+    /// \Z
+    EndOrBeforeFinalNewline,
+}
+
+/// Shorthand class like `\d`, negated when written in upper case.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PerlClass {
+    kind: PerlClassKind,
+    negated: bool,
+}
+
+impl PerlClass {
+    pub fn new(kind: PerlClassKind, negated: bool) -> PerlClass {
+        PerlClass { kind, negated }
+    }
+
+    pub fn kind(&self) -> PerlClassKind {
+        self.kind
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PerlClassKind {
+    /// \d
+    Digit,
+    /// \s
+    Space,
+    /// \w
+    Word,
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bracket {
     exprs: Vec<BracketExpr>,
     negated: bool,
 }
 
 impl Bracket {
+    pub fn new(exprs: Vec<BracketExpr>, negated: bool) -> Bracket {
+        Bracket { exprs, negated }
+    }
+
     pub fn exprs(&self) -> &[BracketExpr] {
         &self.exprs
     }
 
+    pub fn exprs_mut(&mut self) -> &mut Vec<BracketExpr> {
+        &mut self.exprs
+    }
+
     pub fn negated(&self) -> bool {
         self.negated
     }
+
+    /// Whether any of the expressions is a Unicode property, which can't be
+    /// matched yet.
+    pub fn has_unicode_property(&self) -> bool {
+        self.exprs
+            .iter()
+            .any(|expr| matches!(expr, BracketExpr::UnicodeProperty { .. }))
+    }
+
+    /// Bracket matching every character this one doesn't, like `[^a-z]` for
+    /// `[a-z]`.
+    pub fn negate(&self) -> Bracket {
+        Bracket::new(self.exprs.clone(), !self.negated)
+    }
+
+    /// Like `negate`, but lists the characters of `alphabet` this bracket
+    /// doesn't match instead, with named classes in Unicode mode if `unicode`.
+    /// Unicode properties are ignored, like in `CharClass::from_bracket`.
+    pub fn complement(&self, alphabet: &CharClass, unicode: bool) -> Bracket {
+        // Outside the alphabet or matched by this bracket, then negated.
+        let mut excluded = alphabet.clone();
+        excluded.negate();
+        excluded.union(&CharClass::from_bracket(self, unicode));
+        excluded.negate();
+        let exprs = excluded.ranges().iter().map(|&(a, b)| {
+            if a == b {
+                BracketExpr::Char(a)
+            } else {
+                BracketExpr::Range(a, b)
+            }
+        });
+        Bracket::new(exprs.collect(), false)
+    }
+}
+
+impl Extend<BracketExpr> for Bracket {
+    fn extend<I: IntoIterator<Item = BracketExpr>>(&mut self, iter: I) {
+        self.exprs.extend(iter)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BracketExpr {
     Char(char),
     Range(char, char),
-    Class(Class),
+    /// Named class, or everything outside of it if negated.
+    Class(Class, bool),
+    /// Unicode general category, like `\p{Lu}`, or its complement if negated.
+    UnicodeProperty {
+        name: String,
+        negated: bool,
+    },
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Class {
     Alnum,
     Alpha,
@@ -75,44 +229,91 @@ pub enum Class {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Concatenation {
     items: Vec<Ast>,
 }
 
 impl Concatenation {
+    pub fn new(items: Vec<Ast>) -> Concatenation {
+        Concatenation { items }
+    }
+
     pub fn items(&self) -> &[Ast] {
         &self.items
     }
+
+    pub fn items_mut(&mut self) -> &mut Vec<Ast> {
+        &mut self.items
+    }
+
+    pub fn into_items(self) -> Vec<Ast> {
+        self.items
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Alternative {
     items: Vec<Ast>,
 }
 
 impl Alternative {
+    pub fn new(items: Vec<Ast>) -> Alternative {
+        Alternative { items }
+    }
+
     pub fn items(&self) -> &[Ast] {
         &self.items
     }
+
+    pub fn items_mut(&mut self) -> &mut Vec<Ast> {
+        &mut self.items
+    }
+
+    pub fn into_items(self) -> Vec<Ast> {
+        self.items
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Repetition {
     inner: Box<Ast>,
     quantifier: Quantifier,
 }
 
 impl Repetition {
+    pub fn new(inner: Ast, quantifier: Quantifier) -> Repetition {
+        Repetition {
+            inner: Box::new(inner),
+            quantifier,
+        }
+    }
+
     pub fn inner(&self) -> &Ast {
         &self.inner
     }
 
+    pub fn inner_mut(&mut self) -> &mut Ast {
+        &mut self.inner
+    }
+
     pub fn quantifier(&self) -> Quantifier {
         self.quantifier
     }
+
+    pub fn set_quantifier(&mut self, quantifier: Quantifier) {
+        self.quantifier = quantifier;
+    }
+
+    pub fn into_inner(self) -> Ast {
+        *self.inner
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Quantifier {
     /// ?
     ZeroOrOne,
@@ -124,23 +325,460 @@ pub enum Quantifier {
     Exact(u8),
     /// {n,}
     Minimum(u8),
-    /// {n,m}
+    /// {n,m}, or {,m} with n = 0
     Range(u8, u8),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Group {
     inner: Box<Ast>,
+    kind: GroupKind,
 }
 
 impl Group {
+    /// Capturing group.
+    pub fn new(inner: Ast) -> Group {
+        Group::with_kind(inner, GroupKind::Capturing)
+    }
+
+    pub fn with_kind(inner: Ast, kind: GroupKind) -> Group {
+        Group {
+            inner: Box::new(inner),
+            kind,
+        }
+    }
+
     pub fn inner(&self) -> &Ast {
         &self.inner
     }
+
+    pub fn inner_mut(&mut self) -> &mut Ast {
+        &mut self.inner
+    }
+
+    pub fn kind(&self) -> &GroupKind {
+        &self.kind
+    }
+
+    pub fn into_parts(self) -> (Ast, GroupKind) {
+        (*self.inner, self.kind)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GroupKind {
+    /// (...)
+    Capturing,
+    /// (?P<name>...) or (?<name>...)
+    Named(String),
+    /// (?flags:...)
+    NonCapturing(Flags),
+}
+
+impl GroupKind {
+    pub fn is_capturing(&self) -> bool {
+        !matches!(self, GroupKind::NonCapturing(_))
+    }
+}
+
+/// Zero-width assertion that the inner expression matches, or doesn't if
+/// negated, next to the current position.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookAround {
+    inner: Box<Ast>,
+    direction: LookDirection,
+    negated: bool,
+}
+
+impl LookAround {
+    pub fn new(inner: Ast, direction: LookDirection, negated: bool) -> LookAround {
+        LookAround {
+            inner: Box::new(inner),
+            direction,
+            negated,
+        }
+    }
+
+    pub fn inner(&self) -> &Ast {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Ast {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> Ast {
+        *self.inner
+    }
+
+    pub fn direction(&self) -> LookDirection {
+        self.direction
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+}
+
+/// Text matched by the named group, like `\k<name>`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Backreference {
+    name: String,
+}
+
+impl Backreference {
+    pub fn new(name: String) -> Backreference {
+        Backreference { name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LookDirection {
+    /// (?=...) or (?!...)
+    Ahead,
+    /// (?<=...) or (?<!...)
+    Behind,
+}
+
+/// Inline flags, each either set, cleared or left unchanged.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Flags {
+    case_insensitive: Option<bool>,
+    multi_line: Option<bool>,
+    dot_matches_new_line: Option<bool>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    unicode: Option<bool>,
+}
+
+impl Flags {
+    pub fn new(
+        case_insensitive: Option<bool>,
+        multi_line: Option<bool>,
+        dot_matches_new_line: Option<bool>,
+        unicode: Option<bool>,
+    ) -> Flags {
+        Flags {
+            case_insensitive,
+            multi_line,
+            dot_matches_new_line,
+            unicode,
+        }
+    }
+
+    /// i
+    pub fn case_insensitive(&self) -> Option<bool> {
+        self.case_insensitive
+    }
+
+    /// m
+    pub fn multi_line(&self) -> Option<bool> {
+        self.multi_line
+    }
+
+    /// s
+    pub fn dot_matches_new_line(&self) -> Option<bool> {
+        self.dot_matches_new_line
+    }
+
+    /// u
+    pub fn unicode(&self) -> Option<bool> {
+        self.unicode
+    }
+
+    fn set(&mut self, flag: char, value: bool) {
+        match flag {
+            'i' => self.case_insensitive = Some(value),
+            'm' => self.multi_line = Some(value),
+            's' => self.dot_matches_new_line = Some(value),
+            'u' => self.unicode = Some(value),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ast::Literal(x) => x.fmt(f),
+            Ast::Literals(x) => x.fmt(f),
+            Ast::Wildcard(x) => x.fmt(f),
+            Ast::Anchor(x) => x.fmt(f),
+            Ast::PerlClass(x) => x.fmt(f),
+            Ast::Flags(x) => write!(f, "(?{})", x),
+            Ast::Bracket(x) => x.fmt(f),
+            Ast::Concatenation(x) => x.fmt(f),
+            Ast::Alternative(x) => x.fmt(f),
+            Ast::Group(x) => x.fmt(f),
+            Ast::Repetition(x) => x.fmt(f),
+            Ast::Look(x) => x.fmt(f),
+            Ast::Atomic(x) => write!(f, "(?>{})", x),
+            Ast::Backreference(x) => x.fmt(f),
+        }
+    }
+}
+
+/// Writes `c` as an escape sequence if it has one, with `special` characters
+/// escaped by a backslash.
+fn write_char(f: &mut fmt::Formatter, c: char, special: &str) -> fmt::Result {
+    match c {
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\t' => f.write_str("\\t"),
+        '\x07' => f.write_str("\\a"),
+        '\x1b' => f.write_str("\\e"),
+        '\x0c' => f.write_str("\\f"),
+        '\x01'..='\x1a' => write!(f, "\\c{}", char::from(c as u8 + b'@')),
+        c if special.contains(c) => write!(f, "\\{}", c),
+        c => write!(f, "{}", c),
+    }
+}
+
+const LITERAL_SPECIAL: &str = "\\|.?+*(){}[]^$";
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_char(f, self.value, LITERAL_SPECIAL)
+    }
+}
+
+impl fmt::Display for Literals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.value.chars() {
+            write_char(f, c, LITERAL_SPECIAL)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Wildcard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(".")
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Anchor::Start => "^",
+            Anchor::End => "$",
+            Anchor::StartOfText => r"\A",
+            Anchor::EndOfText => r"\z",
+            Anchor::EndOrBeforeFinalNewline => r"\Z",
+        })
+    }
+}
+
+impl fmt::Display for PerlClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self.kind {
+            PerlClassKind::Digit => 'd',
+            PerlClassKind::Space => 's',
+            PerlClassKind::Word => 'w',
+        };
+        if self.negated {
+            write!(f, "\\{}", c.to_ascii_uppercase())
+        } else {
+            write!(f, "\\{}", c)
+        }
+    }
+}
+
+/// Characters escaped inside brackets. A lone `-` is written first or last
+/// instead, where it needs no escape.
+const BRACKET_SPECIAL: &str = "\\[]^";
+
+/// Characters escaped as range endpoints, where a `-` would be ambiguous.
+const RANGE_SPECIAL: &str = "\\[]^-";
+
+impl fmt::Display for Bracket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dash = BracketExpr::Char('-');
+        f.write_str(if self.negated { "[^" } else { "[" })?;
+        if self.exprs.first() == Some(&dash) {
+            f.write_str("-")?;
+        }
+        for expr in self.exprs.iter().filter(|&expr| *expr != dash) {
+            expr.fmt(f)?;
+        }
+        if self.exprs.iter().skip(1).any(|expr| *expr == dash) {
+            f.write_str("-")?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl fmt::Display for BracketExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BracketExpr::Char(c) => write_char(f, *c, BRACKET_SPECIAL),
+            BracketExpr::Range(a, b) => {
+                write_char(f, *a, RANGE_SPECIAL)?;
+                f.write_str("-")?;
+                write_char(f, *b, RANGE_SPECIAL)
+            }
+            BracketExpr::Class(class, false) => write!(f, "[:{}:]", class),
+            BracketExpr::Class(class, true) => write!(f, "[:^{}:]", class),
+            BracketExpr::UnicodeProperty {
+                name,
+                negated: false,
+            } => write!(f, "\\p{{{}}}", name),
+            BracketExpr::UnicodeProperty {
+                name,
+                negated: true,
+            } => write!(f, "\\P{{{}}}", name),
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Class::*;
+        f.write_str(match self {
+            Alnum => "alnum",
+            Alpha => "alpha",
+            Blank => "blank",
+            Cntrl => "cntrl",
+            Digit => "digit",
+            Graph => "graph",
+            Lower => "lower",
+            Print => "print",
+            Punct => "punct",
+            Space => "space",
+            Upper => "upper",
+            Xdigit => "xdigit",
+        })
+    }
+}
+
+impl fmt::Display for Concatenation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Matches the empty string, which otherwise wouldn't parse back.
+        if self.items.is_empty() {
+            return f.write_str("(?:)");
+        }
+        for item in &self.items {
+            match item {
+                Ast::Alternative(_) => write!(f, "(?:{})", item)?,
+                _ => item.fmt(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Alternative {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                f.write_str("|")?;
+            }
+            item.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Repetition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &*self.inner {
+            Ast::Literals(x) if x.value.chars().count() != 1 => {
+                write!(f, "(?:{}){}", self.inner, self.quantifier)
+            }
+            Ast::Concatenation(x) if x.items.is_empty() => {
+                write!(f, "{}{}", self.inner, self.quantifier)
+            }
+            Ast::Concatenation(_) | Ast::Alternative(_) | Ast::Repetition(_) => {
+                write!(f, "(?:{}){}", self.inner, self.quantifier)
+            }
+            _ => write!(f, "{}{}", self.inner, self.quantifier),
+        }
+    }
+}
+
+impl fmt::Display for Quantifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quantifier::ZeroOrOne => f.write_str("?"),
+            Quantifier::ZeroOrMore => f.write_str("*"),
+            Quantifier::OneOrMore => f.write_str("+"),
+            Quantifier::Exact(n) => write!(f, "{{{}}}", n),
+            Quantifier::Minimum(n) => write!(f, "{{{},}}", n),
+            Quantifier::Range(n, m) => write!(f, "{{{},{}}}", n, m),
+        }
+    }
 }
 
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            GroupKind::Capturing => write!(f, "({})", self.inner),
+            GroupKind::Named(name) => write!(f, "(?P<{}>{})", name, self.inner),
+            GroupKind::NonCapturing(flags) => write!(f, "(?{}:{})", flags, self.inner),
+        }
+    }
+}
+
+impl fmt::Display for LookAround {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let open = match (self.direction, self.negated) {
+            (LookDirection::Ahead, false) => "(?=",
+            (LookDirection::Ahead, true) => "(?!",
+            (LookDirection::Behind, false) => "(?<=",
+            (LookDirection::Behind, true) => "(?<!",
+        };
+        write!(f, "{}{})", open, self.inner)
+    }
+}
+
+impl fmt::Display for Backreference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\\k<{}>", self.name)
+    }
+}
+
+/// Writes the flags as they appear after `(?`, like `i-ms`.
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            ('i', self.case_insensitive),
+            ('m', self.multi_line),
+            ('s', self.dot_matches_new_line),
+            ('u', self.unicode),
+        ];
+        for &(flag, value) in &flags {
+            if value == Some(true) {
+                write!(f, "{}", flag)?;
+            }
+        }
+        if flags.iter().any(|&(_, value)| value == Some(false)) {
+            f.write_str("-")?;
+            for &(flag, value) in &flags {
+                if value == Some(false) {
+                    write!(f, "{}", flag)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Repetition count, failing with `Digit` if it doesn't fit.
 fn number(i: &str) -> IResult<&str, u8> {
-    map(digit1, |s| u8::from_str(s).unwrap())(i)
+    let (rest, digits) = digit1(i)?;
+    match u8::from_str(digits) {
+        Ok(n) => Ok((rest, n)),
+        Err(_) => Err(nom::Err::Failure((i, ErrorKind::Digit))),
+    }
 }
 
 fn range(i: &str) -> IResult<&str, Quantifier> {
@@ -150,6 +788,7 @@ fn range(i: &str) -> IResult<&str, Quantifier> {
         }),
         map(terminated(number, char(',')), Quantifier::Minimum),
         map(number, Quantifier::Exact),
+        map(preceded(char(','), number), |m| Quantifier::Range(0, m)),
     ))(i)
 }
 
@@ -162,13 +801,172 @@ fn quantifier(i: &str) -> IResult<&str, Quantifier> {
     ))(i)
 }
 
-fn group(i: &str) -> IResult<&str, Ast> {
-    map(delimited(char('('), re, char(')')), |x| {
-        Ast::Group(Group { inner: Box::new(x) })
-    })(i)
+// nom only collects into vectors with its `alloc` feature, which needs a
+// nightly compiler without `std`, so these stand in for its combinators.
+
+fn many0<'a, O: Clone, F>(f: F) -> impl Fn(&'a str) -> IResult<&'a str, Vec<O>>
+where
+    F: Fn(&'a str) -> IResult<&'a str, O>,
+{
+    fold_many0(f, Vec::new(), push)
+}
+
+fn many1<'a, O: Clone, F>(f: F) -> impl Fn(&'a str) -> IResult<&'a str, Vec<O>>
+where
+    F: Fn(&'a str) -> IResult<&'a str, O>,
+{
+    fold_many1(f, Vec::new(), push)
+}
+
+fn separated_nonempty_list<'a, O: Clone, O2, F, G>(
+    sep: G,
+    f: F,
+) -> impl Fn(&'a str) -> IResult<&'a str, Vec<O>>
+where
+    F: Fn(&'a str) -> IResult<&'a str, O>,
+    G: Fn(&'a str) -> IResult<&'a str, O2>,
+{
+    move |i| {
+        let (i, first) = f(i)?;
+        fold_many0(preceded(&sep, &f), Vec::from([first]), push)(i)
+    }
+}
+
+fn push<T>(mut items: Vec<T>, item: T) -> Vec<T> {
+    items.push(item);
+    items
+}
+
+fn flags(i: &str) -> IResult<&str, Flags> {
+    map(
+        verify(
+            pair(
+                many0(one_of("imsu")),
+                opt(preceded(char('-'), many1(one_of("imsu")))),
+            ),
+            |(set, clear)| !set.is_empty() || clear.is_some(),
+        ),
+        |(set, clear)| {
+            let mut flags = Flags::default();
+            for flag in set {
+                flags.set(flag, true);
+            }
+            for flag in clear.unwrap_or_default() {
+                flags.set(flag, false);
+            }
+            flags
+        },
+    )(i)
+}
+
+fn set_flags(i: &str) -> IResult<&str, Ast> {
+    map(delimited(tag("(?"), flags, char(')')), Ast::Flags)(i)
+}
+
+fn group_name(i: &str) -> IResult<&str, &str> {
+    verify(
+        take_while1(|c: char| c == '_' || c.is_ascii_alphanumeric()),
+        |name: &str| !name.starts_with(|c: char| c.is_ascii_digit()),
+    )(i)
+}
+
+/// How deeply groups may nest by default. Each level takes several kilobytes
+/// of stack in debug builds, so this stays well within a 2 MiB thread stack.
+pub const DEFAULT_NEST_LIMIT: usize = 100;
+
+/// `re` inside a group, failing if no more groups may nest in it.
+/// Body of the group opened at `open`. Input ending right after the opening
+/// fails like in `close_group`.
+fn nested_re<'a>(i: &'a str, open: &'a str, nest_limit: usize) -> IResult<&'a str, Ast> {
+    if i.is_empty() {
+        return Err(nom::Err::Failure((open, ErrorKind::Eof)));
+    }
+    match nest_limit.checked_sub(1) {
+        Some(nest_limit) => re_within(i, nest_limit),
+        None => Err(nom::Err::Failure((i, ErrorKind::TooLarge))),
+    }
+}
+
+/// `)` closing the group opened at `open`, failing outright if the input ends
+/// instead, so that the group is reported as unbalanced.
+fn close_group<'a>(open: &'a str) -> impl Fn(&'a str) -> IResult<&'a str, char> + Copy {
+    move |i| {
+        if i.is_empty() {
+            return Err(nom::Err::Failure((open, ErrorKind::Eof)));
+        }
+        char(')')(i)
+    }
+}
+
+fn look_around(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let nested_re = |rest| nested_re(rest, i, nest_limit);
+    map(
+        tuple((
+            alt((
+                map(tag("(?="), |_| (LookDirection::Ahead, false)),
+                map(tag("(?!"), |_| (LookDirection::Ahead, true)),
+                map(tag("(?<="), |_| (LookDirection::Behind, false)),
+                map(tag("(?<!"), |_| (LookDirection::Behind, true)),
+            )),
+            nested_re,
+            close_group(i),
+        )),
+        |((direction, negated), x, _)| Ast::Look(LookAround::new(x, direction, negated)),
+    )(i)
+}
+
+fn atomic(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let nested_re = |rest| nested_re(rest, i, nest_limit);
+    map(
+        tuple((tag("(?>"), nested_re, close_group(i))),
+        |(_, x, _)| Ast::Atomic(Box::new(x)),
+    )(i)
+}
+
+fn group(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let nested_re = |rest| nested_re(rest, i, nest_limit);
+    let close = close_group(i);
+    alt((
+        map(tag("(?:)"), |_| {
+            Ast::Concatenation(Concatenation { items: Vec::new() })
+        }),
+        map(
+            tuple((
+                alt((tag("(?P<"), tag("(?<"))),
+                group_name,
+                char('>'),
+                nested_re,
+                close,
+            )),
+            |(_, name, _, x, _)| {
+                Ast::Group(Group {
+                    inner: Box::new(x),
+                    kind: GroupKind::Named(name.to_string()),
+                })
+            },
+        ),
+        map(
+            tuple((tag("(?"), opt(flags), char(':'), nested_re, close)),
+            |(_, flags, _, x, _)| {
+                Ast::Group(Group {
+                    inner: Box::new(x),
+                    kind: GroupKind::NonCapturing(flags.unwrap_or_default()),
+                })
+            },
+        ),
+        map(delimited(char('('), nested_re, close), |x| {
+            Ast::Group(Group {
+                inner: Box::new(x),
+                kind: GroupKind::Capturing,
+            })
+        }),
+    ))(i)
 }
 
 fn escaped(i: &str) -> IResult<&str, char> {
+    if i == "\\" {
+        return Err(nom::Err::Failure((i, ErrorKind::Escaped)));
+    }
     preceded(
         char('\\'),
         alt((
@@ -179,33 +977,147 @@ fn escaped(i: &str) -> IResult<&str, char> {
             map(char('a'), |_| '\x07'),
             map(char('e'), |_| '\x1b'),
             map(char('f'), |_| '\x0c'),
-            map(char('v'), |_| '\x0b'),
+            preceded(char('c'), control),
             // XXX: unicode codepoints
         )),
     )(i)
 }
 
+/// Letter after `\c`, standing for the control character with its code
+/// modulo 32, so that `\cA` and `\ca` are both 0x01.
+fn control(i: &str) -> IResult<&str, char> {
+    map(verify(anychar, char::is_ascii_alphabetic), |c| {
+        char::from(c as u8 & 0x1f)
+    })(i)
+}
+
 fn literal(i: &str) -> IResult<&str, Ast> {
     map(alt((none_of("\\|.?+*(){}^$"), escaped)), |c| {
         Ast::Literal(Literal { value: c })
     })(i)
 }
 
+fn anchor(i: &str) -> IResult<&str, Ast> {
+    map(
+        alt((
+            map(char('^'), |_| Anchor::Start),
+            map(char('$'), |_| Anchor::End),
+            map(tag(r"\A"), |_| Anchor::StartOfText),
+            map(tag(r"\z"), |_| Anchor::EndOfText),
+            map(tag(r"\Z"), |_| Anchor::EndOrBeforeFinalNewline),
+        )),
+        Ast::Anchor,
+    )(i)
+}
+
+fn perl_class(i: &str) -> IResult<&str, Ast> {
+    map(preceded(char('\\'), one_of("dDsSwW")), |c| {
+        let kind = match c.to_ascii_lowercase() {
+            'd' => PerlClassKind::Digit,
+            's' => PerlClassKind::Space,
+            _ => PerlClassKind::Word,
+        };
+        Ast::PerlClass(PerlClass {
+            kind,
+            negated: c.is_ascii_uppercase(),
+        })
+    })(i)
+}
+
+/// `\h`, matching a space or tab.
+const HORIZONTAL_SPACE: &[BracketExpr] = &[BracketExpr::Char('\t'), BracketExpr::Char(' ')];
+
+/// `\v`, matching a newline, vertical tab, form feed or carriage return.
+const VERTICAL_SPACE: &[BracketExpr] = &[BracketExpr::Range('\n', '\r')];
+
+/// Shorthand standing for the bracket expressions it expands to, both on its
+/// own and inside brackets.
+fn space_class(i: &str) -> IResult<&str, &'static [BracketExpr]> {
+    preceded(
+        char('\\'),
+        alt((
+            map(char('h'), |_| HORIZONTAL_SPACE),
+            map(char('v'), |_| VERTICAL_SPACE),
+        )),
+    )(i)
+}
+
+/// Unicode general categories `\p{...}` accepts.
+const GENERAL_CATEGORIES: &[&str] = &[
+    "C", "Cc", "Cf", "Cn", "Co", "Cs", "L", "Ll", "Lm", "Lo", "Lt", "Lu", "M", "Mc", "Me", "Mn",
+    "N", "Nd", "Nl", "No", "P", "Pc", "Pd", "Pe", "Pf", "Pi", "Po", "Ps", "S", "Sc", "Sk", "Sm",
+    "So", "Z", "Zl", "Zp", "Zs",
+];
+
+/// `\p{name}` or `\P{name}`, failing outright on an unknown name.
+fn unicode_property(i: &str) -> IResult<&str, BracketExpr> {
+    let (i, negated) = delimited(
+        char('\\'),
+        alt((map(char('p'), |_| false), map(char('P'), |_| true))),
+        char('{'),
+    )(i)?;
+    let (rest, name) = terminated(take_while1(|c: char| c.is_ascii_alphanumeric()), char('}'))(i)?;
+    if !GENERAL_CATEGORIES.contains(&name) {
+        return Err(nom::Err::Failure((i, ErrorKind::MapRes)));
+    }
+    let name = name.to_string();
+    Ok((rest, BracketExpr::UnicodeProperty { name, negated }))
+}
+
+/// `\k<name>` or `\k'name'`.
+fn backreference(i: &str) -> IResult<&str, Ast> {
+    map(
+        preceded(
+            tag("\\k"),
+            alt((
+                delimited(char('<'), group_name, char('>')),
+                delimited(char('\''), group_name, char('\'')),
+            )),
+        ),
+        |name| Ast::Backreference(Backreference::new(name.to_string())),
+    )(i)
+}
+
 fn expr(i: &str) -> IResult<&str, Ast> {
     alt((
         bracket,
+        anchor,
+        perl_class,
+        backreference,
+        map(unicode_property, |expr| {
+            Ast::Bracket(Bracket {
+                exprs: Vec::from([expr]),
+                negated: false,
+            })
+        }),
+        map(space_class, |exprs| {
+            Ast::Bracket(Bracket {
+                exprs: exprs.to_vec(),
+                negated: false,
+            })
+        }),
         literal,
         map(char('.'), |_| Ast::Wildcard(Wildcard)),
     ))(i)
 }
 
-fn basic_re(i: &str) -> IResult<&str, Ast> {
-    alt((group, expr))(i)
+fn basic_re(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    alt((
+        set_flags,
+        |i| look_around(i, nest_limit),
+        |i| atomic(i, nest_limit),
+        |i| group(i, nest_limit),
+        expr,
+    ))(i)
 }
 
-fn simple_re(i: &str) -> IResult<&str, Ast> {
-    let (i, ast) = basic_re(i)?;
+fn simple_re(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let (i, ast) = basic_re(i, nest_limit)?;
     let (i, q) = opt(quantifier)(i)?;
+    // Quantifiers only stack around a group, like `(a*)+`.
+    if q.is_some() && quantifier(i).is_ok() {
+        return Err(nom::Err::Failure((i, ErrorKind::Count)));
+    }
     let ret = match q {
         Some(q) => Ast::Repetition(Repetition {
             inner: Box::new(ast),
@@ -216,10 +1128,43 @@ fn simple_re(i: &str) -> IResult<&str, Ast> {
     Ok((i, ret))
 }
 
-fn branch(i: &str) -> IResult<&str, Ast> {
-    let (i, v) = many1(simple_re)(i)?;
+/// Replaces runs of adjacent literals in `items` by `Ast::Literals`.
+pub(crate) fn join_literals(items: Vec<Ast>) -> Vec<Ast> {
+    let mut joined = Vec::with_capacity(items.len());
+    let mut run = String::new();
+    for item in items {
+        match item {
+            Ast::Literal(literal) => run.push(literal.value),
+            Ast::Literals(literals) => run.push_str(&literals.value),
+            item => {
+                push_run(&mut joined, &mut run);
+                joined.push(item);
+            }
+        }
+    }
+    push_run(&mut joined, &mut run);
+    joined
+}
+
+/// Moves `run` to the end of `items`, as a single literal if it's only one
+/// character long.
+fn push_run(items: &mut Vec<Ast>, run: &mut String) {
+    let mut chars = run.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => return,
+        (Some(c), None) => items.push(Ast::Literal(Literal { value: c })),
+        _ => items.push(Ast::Literals(Literals {
+            value: core::mem::take(run),
+        })),
+    }
+    run.clear();
+}
+
+fn branch(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let (i, v) = many1(|i| simple_re(i, nest_limit))(i)?;
+    let mut v = join_literals(v);
     let ret = if v.len() == 1 {
-        v[0].clone()
+        v.pop().unwrap()
     } else {
         Ast::Concatenation(Concatenation { items: v })
     };
@@ -227,15 +1172,132 @@ fn branch(i: &str) -> IResult<&str, Ast> {
 }
 
 pub fn re(i: &str) -> IResult<&str, Ast> {
-    let (i, v) = separated_nonempty_list(char('|'), branch)(i)?;
+    re_within(i, DEFAULT_NEST_LIMIT)
+}
+
+/// `re` with at most `nest_limit` levels of groups.
+fn re_within(i: &str, nest_limit: usize) -> IResult<&str, Ast> {
+    let (i, mut v) = separated_nonempty_list(char('|'), |i| branch(i, nest_limit))(i)?;
     let ret = if v.len() == 1 {
-        v[0].clone()
+        v.pop().unwrap()
     } else {
         Ast::Alternative(Alternative { items: v })
     };
     Ok((i, ret))
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// Unexpected input at the given byte offset.
+    Syntax(usize),
+    /// Two groups share the name.
+    DuplicateGroupName(String),
+    /// Groups are nested deeper than the limit.
+    TooDeep,
+    /// A bracket range at the given byte offset ends before it starts.
+    DescendingRange(usize),
+    /// A quantifier follows another one, as in `a**`.
+    NestedQuantifier,
+    /// The group opened at the given byte offset is never closed.
+    UnbalancedGroup(usize),
+    /// The `)` at the given byte offset doesn't close any group.
+    UnopenedGroup(usize),
+    /// The pattern ends with the backslash at the given byte offset.
+    DanglingEscape(usize),
+    /// `\p{...}` names a Unicode property that doesn't exist.
+    UnknownProperty(String),
+    /// The repetition count at the given byte offset is above 255.
+    CountTooLarge(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Syntax(offset) => write!(f, "syntax error at offset {}", offset),
+            ParseError::DuplicateGroupName(name) => write!(f, "duplicate group name {}", name),
+            ParseError::TooDeep => f.write_str("groups nested too deeply"),
+            ParseError::DescendingRange(offset) => {
+                write!(f, "descending bracket range at offset {}", offset)
+            }
+            ParseError::NestedQuantifier => f.write_str("quantifier follows another quantifier"),
+            ParseError::UnbalancedGroup(offset) => write!(f, "unclosed group at offset {}", offset),
+            ParseError::UnopenedGroup(offset) => write!(f, "unopened group at offset {}", offset),
+            ParseError::DanglingEscape(offset) => {
+                write!(f, "dangling backslash at offset {}", offset)
+            }
+            ParseError::UnknownProperty(name) => write!(f, "unknown Unicode property {}", name),
+            ParseError::CountTooLarge(offset) => {
+                write!(f, "repetition count at offset {} exceeds 255", offset)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Parses the whole pattern, rejecting any trailing input.
+pub fn parse(i: &str) -> Result<Ast, ParseError> {
+    parse_with_limit(i, DEFAULT_NEST_LIMIT)
+}
+
+/// Same as `parse`, but with groups allowed to nest `nest_limit` deep.
+pub fn parse_with_limit(i: &str, nest_limit: usize) -> Result<Ast, ParseError> {
+    match re_within(i, nest_limit) {
+        Ok(("", ast)) => {
+            check_group_names(&ast)?;
+            Ok(ast)
+        }
+        Ok((rest, _)) if rest.starts_with(')') => {
+            Err(ParseError::UnopenedGroup(i.len() - rest.len()))
+        }
+        Ok((rest, _)) => Err(ParseError::Syntax(i.len() - rest.len())),
+        // Nothing parsed before a leading `)`.
+        Err(nom::Err::Error((rest, _))) if rest == i && rest.starts_with(')') => {
+            Err(ParseError::UnopenedGroup(0))
+        }
+        Err(nom::Err::Failure((_, ErrorKind::TooLarge))) => Err(ParseError::TooDeep),
+        Err(nom::Err::Failure((_, ErrorKind::Count))) => Err(ParseError::NestedQuantifier),
+        Err(nom::Err::Failure((rest, ErrorKind::Eof))) => {
+            Err(ParseError::UnbalancedGroup(i.len() - rest.len()))
+        }
+        Err(nom::Err::Failure((rest, ErrorKind::Escaped))) => {
+            Err(ParseError::DanglingEscape(i.len() - rest.len()))
+        }
+        Err(nom::Err::Failure((rest, ErrorKind::MapRes))) => {
+            let name = rest.split('}').next().unwrap_or_default();
+            Err(ParseError::UnknownProperty(name.to_string()))
+        }
+        Err(nom::Err::Failure((rest, ErrorKind::Verify))) => {
+            Err(ParseError::DescendingRange(i.len() - rest.len()))
+        }
+        Err(nom::Err::Failure((rest, ErrorKind::Digit))) => {
+            Err(ParseError::CountTooLarge(i.len() - rest.len()))
+        }
+        Err(nom::Err::Error((rest, _))) | Err(nom::Err::Failure((rest, _))) => {
+            Err(ParseError::Syntax(i.len() - rest.len()))
+        }
+        Err(nom::Err::Incomplete(_)) => Err(ParseError::Syntax(i.len())),
+    }
+}
+
+fn check_group_names(ast: &Ast) -> Result<(), ParseError> {
+    let mut seen = BTreeSet::new();
+    for name in crate::analysis::group_names(ast) {
+        if !seen.insert(name) {
+            return Err(ParseError::DuplicateGroupName(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+impl FromStr for Ast {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Ast, ParseError> {
+        parse(s)
+    }
+}
+
 fn class_name(i: &str) -> IResult<&str, Class> {
     use Class::*;
     alt((
@@ -254,49 +1316,77 @@ fn class_name(i: &str) -> IResult<&str, Class> {
     ))(i)
 }
 
-fn class(i: &str) -> IResult<&str, Class> {
-    delimited(tag("[:"), class_name, tag(":]"))(i)
+/// Named class, with whether it's negated.
+fn class(i: &str) -> IResult<&str, (bool, Class)> {
+    map(
+        delimited(tag("[:"), pair(opt(char('^')), class_name), tag(":]")),
+        |(negation, class)| (negation.is_some(), class),
+    )(i)
 }
 
+/// Character in a bracket, where `\-` is a literal `-` anywhere.
 fn bracket_literal(i: &str) -> IResult<&str, char> {
-    alt((none_of(r"\]-"), escaped))(i)
+    alt((none_of(r"\]-"), escaped, preceded(char('\\'), char('-'))))(i)
 }
 
+/// Range in a bracket, failing outright if it's descending, so the bracket
+/// isn't taken for literals instead.
 fn range_expr(i: &str) -> IResult<&str, (char, char)> {
-    separated_pair(bracket_literal, char('-'), bracket_literal)(i)
+    let (rest, (a, b)) = separated_pair(bracket_literal, char('-'), bracket_literal)(i)?;
+    if a > b {
+        return Err(nom::Err::Failure((i, ErrorKind::Verify)));
+    }
+    Ok((rest, (a, b)))
 }
 
 fn term(i: &str) -> IResult<&str, BracketExpr> {
     alt((
         map(range_expr, |(a, b)| BracketExpr::Range(a, b)),
-        map(class, BracketExpr::Class),
+        map(class, |(negated, class)| BracketExpr::Class(class, negated)),
+        unicode_property,
         map(bracket_literal, BracketExpr::Char),
     ))(i)
 }
 
+/// Terms of a bracket, with shorthands expanded in place.
+fn terms(i: &str) -> IResult<&str, Vec<BracketExpr>> {
+    fold_many1(
+        alt((
+            map(term, |t| Vec::from([t])),
+            map(space_class, <[_]>::to_vec),
+        )),
+        Vec::new(),
+        |mut list: Vec<_>, exprs| {
+            list.extend(exprs);
+            list
+        },
+    )(i)
+}
+
 fn bracket(i: &str) -> IResult<&str, Ast> {
     map(
         delimited(
             char('['),
             tuple((
                 opt(char('^')),
-                opt(one_of("]-")),
-                many1(term),
-                opt(char('-')),
+                verify(
+                    tuple((opt(one_of("]-")), opt(terms), opt(char('-')))),
+                    |(head, list, tail)| head.is_some() || list.is_some() || tail.is_some(),
+                ),
             )),
             char(']'),
         ),
-        |(negation, head, mut list, tail)| {
-            let negated = negation.is_some();
-            if let Some(head) = head {
-                list.insert(0, BracketExpr::Char(head));
-            }
-            if let Some(tail) = tail {
-                list.push(BracketExpr::Char(tail));
-            }
+        |(negation, (head, list, tail))| {
+            // A leading `]` or `-` and a trailing `-` are literal, in place.
+            let exprs = head
+                .map(BracketExpr::Char)
+                .into_iter()
+                .chain(list.unwrap_or_default())
+                .chain(tail.map(BracketExpr::Char))
+                .collect();
             Ast::Bracket(Bracket {
-                exprs: list,
-                negated,
+                exprs,
+                negated: negation.is_some(),
             })
         },
     )(i)
@@ -316,6 +1406,8 @@ mod tests {
         assert_eq!(range("2"), Ok(("", Quantifier::Exact(2))));
         assert_eq!(range("2,"), Ok(("", Quantifier::Minimum(2))));
         assert_eq!(range("2,3"), Ok(("", Quantifier::Range(2, 3))));
+        assert_eq!(range(",3"), Ok(("", Quantifier::Range(0, 3))));
+        assert!(range(",").is_err());
     }
 
     #[test]
@@ -324,34 +1416,34 @@ mod tests {
         assert_eq!(quantifier("*"), Ok(("", Quantifier::ZeroOrMore)));
         assert_eq!(quantifier("+"), Ok(("", Quantifier::OneOrMore)));
         assert_eq!(quantifier("{2}"), Ok(("", Quantifier::Exact(2))));
+        assert_eq!(quantifier("{,3}"), Ok(("", Quantifier::Range(0, 3))));
         assert_eq!(quantifier("{2,}"), Ok(("", Quantifier::Minimum(2))));
         assert_eq!(quantifier("{2,3}"), Ok(("", Quantifier::Range(2, 3))));
     }
 
     #[test]
     fn parse_group() {
-        assert!(group("()").is_err());
+        assert!(group("()", DEFAULT_NEST_LIMIT).is_err());
         assert_eq!(
-            group("(foo)"),
+            group("(foo)", DEFAULT_NEST_LIMIT),
             Ok((
                 "",
                 Ast::Group(Group {
-                    inner: Box::new(Ast::Concatenation(Concatenation {
-                        items: vec![
-                            Ast::Literal(Literal { value: 'f' }),
-                            Ast::Literal(Literal { value: 'o' }),
-                            Ast::Literal(Literal { value: 'o' }),
-                        ]
+                    kind: GroupKind::Capturing,
+                    inner: Box::new(Ast::Literals(Literals {
+                        value: "foo".to_string()
                     }))
                 })
             ))
         );
         assert_eq!(
-            group("((x))"),
+            group("((x))", DEFAULT_NEST_LIMIT),
             Ok((
                 "",
                 Ast::Group(Group {
+                    kind: GroupKind::Capturing,
                     inner: Box::new(Ast::Group(Group {
+                        kind: GroupKind::Capturing,
                         inner: Box::new(Ast::Literal(Literal { value: 'x' })),
                     })),
                 })
@@ -359,6 +1451,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_non_capturing_group() {
+        assert_eq!(
+            group("(?:x)", DEFAULT_NEST_LIMIT),
+            Ok((
+                "",
+                Ast::Group(Group {
+                    inner: Box::new(Ast::Literal(Literal { value: 'x' })),
+                    kind: GroupKind::NonCapturing(Flags::default()),
+                })
+            ))
+        );
+        assert_eq!(
+            group("(?i-s:x)", DEFAULT_NEST_LIMIT),
+            Ok((
+                "",
+                Ast::Group(Group {
+                    inner: Box::new(Ast::Literal(Literal { value: 'x' })),
+                    kind: GroupKind::NonCapturing(Flags {
+                        case_insensitive: Some(true),
+                        multi_line: None,
+                        dot_matches_new_line: Some(false),
+                        unicode: None,
+                    }),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_flags() {
+        assert_eq!(
+            flags("im"),
+            Ok((
+                "",
+                Flags {
+                    case_insensitive: Some(true),
+                    multi_line: Some(true),
+                    dot_matches_new_line: None,
+                    unicode: None,
+                }
+            ))
+        );
+        assert_eq!(
+            flags("-s"),
+            Ok((
+                "",
+                Flags {
+                    case_insensitive: None,
+                    multi_line: None,
+                    dot_matches_new_line: Some(false),
+                    unicode: None,
+                }
+            ))
+        );
+        assert!(flags("").is_err());
+        assert!(flags("x").is_err());
+        assert_eq!(
+            set_flags("(?i)a"),
+            Ok((
+                "a",
+                Ast::Flags(Flags {
+                    case_insensitive: Some(true),
+                    multi_line: None,
+                    dot_matches_new_line: None,
+                    unicode: None,
+                })
+            ))
+        );
+        assert!(set_flags("(?)").is_err());
+    }
+
     #[test]
     fn parse_literal() {
         assert_eq!(
@@ -371,6 +1535,23 @@ mod tests {
         );
         assert!(literal("\\").is_err());
         assert!(literal(".").is_err());
+        assert_eq!(
+            literal(r"\cA"),
+            Ok(("", Ast::Literal(Literal::new('\x01'))))
+        );
+        assert_eq!(
+            literal(r"\ca"),
+            Ok(("", Ast::Literal(Literal::new('\x01'))))
+        );
+        assert_eq!(literal(r"\cI"), Ok(("", Ast::Literal(Literal::new('\t')))));
+        assert_eq!(
+            literal(r"\cz"),
+            Ok(("", Ast::Literal(Literal::new('\x1a'))))
+        );
+        assert!(literal(r"\c1").is_err());
+        assert!(literal(r"\c").is_err());
+        assert_eq!(parse(r"\c["), Err(ParseError::Syntax(0)));
+        assert_eq!(parse(r"[\cB]").unwrap().to_string(), r"[\cB]");
         assert_eq!(
             literal(" x"),
             Ok(("x", Ast::Literal(Literal { value: ' ' })))
@@ -386,42 +1567,82 @@ mod tests {
         assert_eq!(expr(".x"), Ok(("x", Ast::Wildcard(Wildcard))));
     }
 
+    #[test]
+    fn parse_anchor() {
+        assert_eq!(anchor("^a"), Ok(("a", Ast::Anchor(Anchor::Start))));
+        assert_eq!(anchor("$"), Ok(("", Ast::Anchor(Anchor::End))));
+        assert_eq!(anchor(r"\A"), Ok(("", Ast::Anchor(Anchor::StartOfText))));
+        assert_eq!(anchor(r"\z"), Ok(("", Ast::Anchor(Anchor::EndOfText))));
+        assert_eq!(
+            anchor(r"\Z"),
+            Ok(("", Ast::Anchor(Anchor::EndOrBeforeFinalNewline)))
+        );
+        assert!(anchor("a").is_err());
+    }
+
+    #[test]
+    fn parse_perl_class() {
+        assert_eq!(
+            perl_class(r"\d+"),
+            Ok((
+                "+",
+                Ast::PerlClass(PerlClass {
+                    kind: PerlClassKind::Digit,
+                    negated: false,
+                })
+            ))
+        );
+        assert_eq!(
+            perl_class(r"\W"),
+            Ok((
+                "",
+                Ast::PerlClass(PerlClass {
+                    kind: PerlClassKind::Word,
+                    negated: true,
+                })
+            ))
+        );
+        assert!(perl_class(r"\n").is_err());
+    }
+
     #[test]
     fn parse_basic_re() {
         assert_eq!(
-            basic_re("(f)oo"),
+            basic_re("(f)oo", DEFAULT_NEST_LIMIT),
             Ok((
                 "oo",
                 Ast::Group(Group {
+                    kind: GroupKind::Capturing,
                     inner: Box::new(Ast::Literal(Literal { value: 'f' }))
                 })
             ))
         );
-        assert_eq!(basic_re(".oof"), Ok(("oof", Ast::Wildcard(Wildcard))));
+        assert_eq!(
+            basic_re(".oof", DEFAULT_NEST_LIMIT),
+            Ok(("oof", Ast::Wildcard(Wildcard)))
+        );
     }
 
     #[test]
     fn parse_simple_re() {
         assert_eq!(
-            simple_re("foo"),
+            simple_re("foo", DEFAULT_NEST_LIMIT),
             Ok(("oo", Ast::Literal(Literal { value: 'f' })))
         );
         assert_eq!(
-            simple_re("(ab)c"),
+            simple_re("(ab)c", DEFAULT_NEST_LIMIT),
             Ok((
                 "c",
                 Ast::Group(Group {
-                    inner: Box::new(Ast::Concatenation(Concatenation {
-                        items: vec![
-                            Ast::Literal(Literal { value: 'a' }),
-                            Ast::Literal(Literal { value: 'b' }),
-                        ]
+                    kind: GroupKind::Capturing,
+                    inner: Box::new(Ast::Literals(Literals {
+                        value: "ab".to_string()
                     }))
                 })
             ))
         );
         assert_eq!(
-            simple_re(".+."),
+            simple_re(".+.", DEFAULT_NEST_LIMIT),
             Ok((
                 ".",
                 Ast::Repetition(Repetition {
@@ -432,23 +1653,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_lookahead() {
+        let look = |negated| {
+            Ast::Concatenation(Concatenation::new(vec![
+                Ast::Literal(Literal::new('a')),
+                Ast::Look(LookAround::new(
+                    Ast::Literal(Literal::new('b')),
+                    LookDirection::Ahead,
+                    negated,
+                )),
+            ]))
+        };
+        assert_eq!(parse("a(?=b)"), Ok(look(false)));
+        assert_eq!(parse("a(?!b)"), Ok(look(true)));
+        assert_eq!(parse("(?=a|b)c").unwrap().to_string(), "(?=a|b)c");
+        assert!(matches!(parse("(?!x*)+"), Ok(Ast::Repetition(_))));
+        assert!(parse("(?=a").is_err());
+    }
+
+    #[test]
+    fn parse_lookbehind() {
+        let look = |negated| {
+            Ast::Concatenation(Concatenation::new(vec![
+                Ast::Look(LookAround::new(
+                    Ast::Literal(Literal::new('a')),
+                    LookDirection::Behind,
+                    negated,
+                )),
+                Ast::Literal(Literal::new('b')),
+            ]))
+        };
+        assert_eq!(parse("(?<=a)b"), Ok(look(false)));
+        assert_eq!(parse("(?<!a)b"), Ok(look(true)));
+        assert_eq!(parse("(?<!a|bc)").unwrap().to_string(), "(?<!a|bc)");
+        assert_eq!(
+            parse("(?<name>a)"),
+            Ok(Ast::Group(Group::with_kind(
+                Ast::Literal(Literal::new('a')),
+                GroupKind::Named("name".to_string()),
+            )))
+        );
+        assert!(matches!(parse("(?<=name>a)"), Ok(Ast::Look(_))));
+        assert!(parse("(?<>a)").is_err());
+    }
+
+    #[test]
+    fn parse_atomic() {
+        assert_eq!(
+            parse("(?>a|ab)"),
+            Ok(Ast::Atomic(Box::new(Ast::Alternative(Alternative::new(
+                vec![
+                    Ast::Literal(Literal::new('a')),
+                    Ast::Literals(Literals::new("ab".to_string())),
+                ]
+            )))))
+        );
+        assert_eq!(parse("(?>a|ab)c").unwrap().to_string(), "(?>a|ab)c");
+        assert!(parse("(?>a").is_err());
+    }
+
+    #[test]
+    fn nested_quantifier() {
+        for pattern in ["a**", "a+*", "a{2}?", "(a)??", "x|[a]*{3}"] {
+            assert_eq!(
+                parse(pattern),
+                Err(ParseError::NestedQuantifier),
+                "{}",
+                pattern
+            );
+        }
+        assert!(parse("(a*)+").is_ok());
+        assert!(parse("(?:a{2})?b*").is_ok());
+    }
+
     #[test]
     fn parse_branch() {
         assert_eq!(
-            branch("foo"),
+            branch("a|b", DEFAULT_NEST_LIMIT),
+            Ok(("|b", Ast::Literal(Literal { value: 'a' })))
+        );
+        assert_eq!(
+            branch("(?:a|b)+", DEFAULT_NEST_LIMIT),
             Ok((
                 "",
-                Ast::Concatenation(Concatenation {
-                    items: vec![
-                        Ast::Literal(Literal { value: 'f' }),
-                        Ast::Literal(Literal { value: 'o' }),
-                        Ast::Literal(Literal { value: 'o' }),
-                    ]
+                Ast::Repetition(Repetition::new(
+                    Ast::Group(Group::with_kind(
+                        parse("a|b").unwrap(),
+                        GroupKind::NonCapturing(Flags::default())
+                    )),
+                    Quantifier::OneOrMore
+                ))
+            ))
+        );
+        assert_eq!(
+            branch("foo", DEFAULT_NEST_LIMIT),
+            Ok((
+                "",
+                Ast::Literals(Literals {
+                    value: "foo".to_string()
                 })
             ))
         );
         assert_eq!(
-            branch("a.?b"),
+            branch("a.?b", DEFAULT_NEST_LIMIT),
             Ok((
                 "",
                 Ast::Concatenation(Concatenation {
@@ -468,6 +1776,7 @@ mod tests {
     #[test]
     fn parse_re() {
         assert_eq!(re("a"), Ok(("", Ast::Literal(Literal { value: 'a' }))));
+        assert_eq!(re("ab)"), Ok((")", parse("ab").unwrap())));
         assert_eq!(
             re("a|b|c"),
             Ok((
@@ -495,6 +1804,7 @@ mod tests {
                             items: vec![
                                 Ast::Wildcard(Wildcard),
                                 Ast::Group(Group {
+                                    kind: GroupKind::Capturing,
                                     inner: Box::new(Ast::Literal(Literal { value: 'b' }))
                                 }),
                             ]
@@ -505,6 +1815,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_group_quantifier() {
+        assert_eq!(
+            re("(a|b)*"),
+            Ok((
+                "",
+                Ast::Repetition(Repetition {
+                    inner: Box::new(Ast::Group(Group {
+                        kind: GroupKind::Capturing,
+                        inner: Box::new(Ast::Alternative(Alternative {
+                            items: vec![
+                                Ast::Literal(Literal { value: 'a' }),
+                                Ast::Literal(Literal { value: 'b' }),
+                            ]
+                        }))
+                    })),
+                    quantifier: Quantifier::ZeroOrMore,
+                })
+            ))
+        );
+        assert_eq!(
+            re("(ab)*c"),
+            Ok((
+                "",
+                Ast::Concatenation(Concatenation {
+                    items: vec![
+                        Ast::Repetition(Repetition {
+                            inner: Box::new(Ast::Group(Group {
+                                kind: GroupKind::Capturing,
+                                inner: Box::new(Ast::Literals(Literals {
+                                    value: "ab".to_string()
+                                }))
+                            })),
+                            quantifier: Quantifier::ZeroOrMore,
+                        }),
+                        Ast::Literal(Literal { value: 'c' }),
+                    ]
+                })
+            ))
+        );
+        assert_eq!(
+            re("a(b|c)+d"),
+            Ok((
+                "",
+                Ast::Concatenation(Concatenation {
+                    items: vec![
+                        Ast::Literal(Literal { value: 'a' }),
+                        Ast::Repetition(Repetition {
+                            inner: Box::new(Ast::Group(Group {
+                                kind: GroupKind::Capturing,
+                                inner: Box::new(Ast::Alternative(Alternative {
+                                    items: vec![
+                                        Ast::Literal(Literal { value: 'b' }),
+                                        Ast::Literal(Literal { value: 'c' }),
+                                    ]
+                                }))
+                            })),
+                            quantifier: Quantifier::OneOrMore,
+                        }),
+                        Ast::Literal(Literal { value: 'd' }),
+                    ]
+                })
+            ))
+        );
+    }
+
     #[test]
     fn parse_class_name() {
         assert_eq!(class_name("alnum"), Ok(("", Class::Alnum)));
@@ -513,8 +1889,10 @@ mod tests {
 
     #[test]
     fn parse_class() {
-        assert_eq!(class("[:alpha:]"), Ok(("", Class::Alpha)));
+        assert_eq!(class("[:alpha:]"), Ok(("", (false, Class::Alpha))));
+        assert_eq!(class("[:^digit:]"), Ok(("", (true, Class::Digit))));
         assert!(class("[::]").is_err());
+        assert!(class("[:^:]").is_err());
     }
 
     #[test]
@@ -522,11 +1900,44 @@ mod tests {
         assert_eq!(bracket_literal("abc"), Ok(("bc", 'a')));
         assert!(bracket_literal("\\").is_err());
         assert_eq!(bracket_literal("."), Ok(("", '.')));
+        assert_eq!(bracket_literal(r"\-x"), Ok(("x", '-')));
+        assert!(bracket_literal("-").is_err());
+    }
+
+    #[test]
+    fn parse_escaped_dash() {
+        assert_eq!(
+            bracket(r"[a\-z]"),
+            Ok((
+                "",
+                Ast::Bracket(Bracket::new(
+                    vec![
+                        BracketExpr::Char('a'),
+                        BracketExpr::Char('-'),
+                        BracketExpr::Char('z'),
+                    ],
+                    false,
+                )),
+            )),
+        );
+        assert_eq!(
+            bracket(r"[!-\-]"),
+            Ok((
+                "",
+                Ast::Bracket(Bracket::new(vec![BracketExpr::Range('!', '-')], false))
+            )),
+        );
+        assert!(parse(r"a\-").is_err());
     }
 
     #[test]
     fn parse_range_expr() {
         assert_eq!(range_expr("a-bc"), Ok(("c", ('a', 'b'))));
+        assert_eq!(range_expr("a-a"), Ok(("", ('a', 'a'))));
+        assert!(matches!(range_expr("b-a"), Err(nom::Err::Failure(_))));
+        assert!(parse("x[a-a]").is_ok());
+        assert_eq!(parse("x[0z-a]"), Err(ParseError::DescendingRange(3)));
+        assert_eq!(parse("(a|[^b-a])"), Err(ParseError::DescendingRange(5)));
     }
 
     #[test]
@@ -534,14 +1945,188 @@ mod tests {
         assert_eq!(term("a-bc"), Ok(("c", BracketExpr::Range('a', 'b'))));
         assert_eq!(
             term("[:space:]"),
-            Ok(("", BracketExpr::Class(Class::Space))),
+            Ok(("", BracketExpr::Class(Class::Space, false))),
         );
         assert_eq!(term("foo"), Ok(("oo", BracketExpr::Char('f'))));
     }
 
+    #[test]
+    fn parse_bracket_order() {
+        let exprs = |pattern| match bracket(pattern) {
+            Ok(("", Ast::Bracket(bracket))) => bracket.exprs,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(
+            exprs("[]a[:digit:]-]"),
+            vec![
+                BracketExpr::Char(']'),
+                BracketExpr::Char('a'),
+                BracketExpr::Class(Class::Digit, false),
+                BracketExpr::Char('-'),
+            ]
+        );
+        assert_eq!(
+            exprs("[-0-9[:alpha:]x_]"),
+            vec![
+                BracketExpr::Char('-'),
+                BracketExpr::Range('0', '9'),
+                BracketExpr::Class(Class::Alpha, false),
+                BracketExpr::Char('x'),
+                BracketExpr::Char('_'),
+            ]
+        );
+        assert_eq!(
+            exprs("[]-]"),
+            vec![BracketExpr::Char(']'), BracketExpr::Char('-')]
+        );
+        assert_eq!(exprs("[-]"), vec![BracketExpr::Char('-')]);
+        assert_eq!(exprs("[]]"), vec![BracketExpr::Char(']')]);
+        assert_eq!(parse("[]-]").unwrap().to_string(), r"[\]-]");
+    }
+
+    #[test]
+    fn parse_negated_class() {
+        let ast = parse("[[:^space:]x]").unwrap();
+        assert_eq!(
+            ast,
+            Ast::Bracket(Bracket::new(
+                vec![
+                    BracketExpr::Class(Class::Space, true),
+                    BracketExpr::Char('x'),
+                ],
+                false,
+            ))
+        );
+        assert_eq!(ast.to_string(), "[[:^space:]x]");
+    }
+
+    #[test]
+    fn parse_backreference() {
+        let group = Ast::Group(Group::with_kind(
+            Ast::Literal(Literal::new('a')),
+            GroupKind::Named("x".to_string()),
+        ));
+        let backref = Ast::Backreference(Backreference::new("x".to_string()));
+        let ast = Ast::Concatenation(Concatenation::new(vec![group, backref]));
+        assert_eq!(parse(r"(?P<x>a)\k<x>"), Ok(ast.clone()));
+        assert_eq!(parse(r"(?P<x>a)\k'x'"), Ok(ast.clone()));
+        assert_eq!(ast.to_string(), r"(?P<x>a)\k<x>");
+        assert!(parse(r"\k<1x>").is_err());
+        assert!(parse(r"\k<x'").is_err());
+    }
+
+    #[test]
+    fn count_too_large() {
+        assert_eq!(parse("a{256}"), Err(ParseError::CountTooLarge(2)));
+        assert_eq!(parse("a{1000,2000}"), Err(ParseError::CountTooLarge(2)));
+        assert_eq!(parse("a{1,999}"), Err(ParseError::CountTooLarge(4)));
+        assert_eq!(parse("a{,300}"), Err(ParseError::CountTooLarge(3)));
+        assert_eq!("a{300}".parse::<Ast>(), Err(ParseError::CountTooLarge(2)));
+        assert!(parse("a{255}").is_ok());
+    }
+
+    #[test]
+    fn dangling_escape() {
+        assert_eq!(parse("abc\\"), Err(ParseError::DanglingEscape(3)));
+        assert_eq!(parse("[a\\"), Err(ParseError::DanglingEscape(2)));
+        assert_eq!(
+            parse("abc\\\\"),
+            Ok(Ast::Literals(Literals::new("abc\\".to_string())))
+        );
+    }
+
+    #[test]
+    fn unbalanced_groups() {
+        assert_eq!(parse("(a|b"), Err(ParseError::UnbalancedGroup(0)));
+        assert_eq!(parse("x((?:a)(?=b"), Err(ParseError::UnbalancedGroup(7)));
+        assert_eq!(parse("a)b"), Err(ParseError::UnopenedGroup(1)));
+        assert_eq!(parse("(a)|b)"), Err(ParseError::UnopenedGroup(5)));
+        assert_eq!(parse("("), Err(ParseError::UnbalancedGroup(0)));
+        assert_eq!(parse("a(?:"), Err(ParseError::UnbalancedGroup(1)));
+        assert_eq!(parse(")"), Err(ParseError::UnopenedGroup(0)));
+        assert_eq!(parse(")a"), Err(ParseError::UnopenedGroup(0)));
+        assert!(parse("(a|(?P<x>b))c").is_ok());
+    }
+
+    #[test]
+    fn negate_bracket() {
+        let bracket = Bracket::new(vec![BracketExpr::Range('a', 'z')], false);
+        let negated = bracket.negate();
+        assert_eq!(negated.to_string(), "[^a-z]");
+        assert_eq!(negated.negate(), bracket);
+        let class = CharClass::from_bracket(&negated, false);
+        assert!(class.contains('A') && class.contains('{') && class.contains('`'));
+        assert!(!class.contains('a') && !class.contains('m') && !class.contains('z'));
+        let alphabet = CharClass::from_range('\0', '\x7f');
+        let complement = bracket.complement(&alphabet, false);
+        assert!(!complement.negated());
+        assert_eq!(
+            complement.exprs(),
+            [
+                BracketExpr::Range('\0', '`'),
+                BracketExpr::Range('{', '\x7f')
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unicode_property() {
+        let property = |name: &str, negated| BracketExpr::UnicodeProperty {
+            name: name.to_string(),
+            negated,
+        };
+        assert_eq!(
+            parse(r"\p{L}"),
+            Ok(Ast::Bracket(Bracket::new(
+                vec![property("L", false)],
+                false
+            )))
+        );
+        let ast = parse(r"[a\P{Nd}]").unwrap();
+        assert_eq!(
+            ast,
+            Ast::Bracket(Bracket::new(
+                vec![BracketExpr::Char('a'), property("Nd", true)],
+                false,
+            ))
+        );
+        assert_eq!(ast.to_string(), r"[a\P{Nd}]");
+        assert_eq!(
+            parse(r"a\p{Xyz}"),
+            Err(ParseError::UnknownProperty("Xyz".to_string()))
+        );
+        assert_eq!(parse(r"\p{}"), Err(ParseError::Syntax(0)));
+    }
+
+    #[test]
+    fn parse_space_class() {
+        let horizontal = Bracket::new(vec![BracketExpr::Char('\t'), BracketExpr::Char(' ')], false);
+        assert_eq!(expr(r"\h+"), Ok(("+", Ast::Bracket(horizontal))));
+        let vertical = Bracket::new(vec![BracketExpr::Range('\n', '\r')], false);
+        assert_eq!(expr(r"\v"), Ok(("", Ast::Bracket(vertical))));
+        assert_eq!(
+            bracket(r"[a\hb-c]"),
+            Ok((
+                "",
+                Ast::Bracket(Bracket::new(
+                    vec![
+                        BracketExpr::Char('a'),
+                        BracketExpr::Char('\t'),
+                        BracketExpr::Char(' '),
+                        BracketExpr::Range('b', 'c'),
+                    ],
+                    false,
+                )),
+            )),
+        );
+        assert_eq!(parse(r"[^\v]").unwrap().to_string(), r"[^\n-\r]");
+        assert_eq!(parse(r"\cK").unwrap().to_string(), r"\cK");
+    }
+
     #[test]
     fn parse_bracket() {
         assert!(bracket("[]").is_err());
+        assert!(bracket("[^]").is_err());
         assert_eq!(
             bracket("[a]"),
             Ok((
@@ -557,7 +2142,7 @@ mod tests {
             Ok((
                 "",
                 Ast::Bracket(Bracket {
-                    exprs: vec![BracketExpr::Class(Class::Digit)],
+                    exprs: vec![BracketExpr::Class(Class::Digit, false)],
                     negated: false,
                 }),
             )),
@@ -601,4 +2186,191 @@ mod tests {
             )),
         );
     }
+
+    #[test]
+    fn extend_bracket() {
+        let mut bracket = Bracket {
+            exprs: vec![],
+            negated: false,
+        };
+        bracket.extend(vec![BracketExpr::Char('a'), BracketExpr::Range('0', '9')]);
+        bracket.extend(Some(BracketExpr::Class(Class::Space, false)));
+        assert_eq!(
+            bracket.exprs(),
+            &[
+                BracketExpr::Char('a'),
+                BracketExpr::Range('0', '9'),
+                BracketExpr::Class(Class::Space, false),
+            ]
+        );
+        let class = crate::class::CharClass::from_bracket(&bracket, false);
+        assert!(class.contains('a'));
+        assert!(class.contains('5'));
+        assert!(class.contains(' '));
+        assert!(!class.contains('b'));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn serialize() {
+        let json = serde_json::to_value(parse("a(b|c)*").unwrap()).unwrap();
+        let items = &json["Concatenation"]["items"];
+        assert_eq!(items[0], serde_json::json!({ "Literal": { "value": "a" } }));
+        let repetition = &items[1]["Repetition"];
+        assert_eq!(repetition["quantifier"], "ZeroOrMore");
+        let group = &repetition["inner"]["Group"];
+        assert_eq!(group["kind"], "Capturing");
+        assert_eq!(
+            group["inner"]["Alternative"]["items"][1]["Literal"]["value"],
+            "c"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn deserialize_round_trip() {
+        let ast = parse(r"^(?:[[:digit:]a-f]{2,4}|x\.)+$").unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        assert_eq!(serde_json::from_str::<Ast>(&json).unwrap(), ast);
+    }
+
+    #[test]
+    fn parse_named_group() {
+        let named = |name: &str| {
+            Ast::Group(Group::with_kind(
+                Ast::Literal(Literal::new('x')),
+                GroupKind::Named(name.to_string()),
+            ))
+        };
+        assert_eq!(parse("(?P<first>x)"), Ok(named("first")));
+        assert_eq!(parse("(?<_a1>x)"), Ok(named("_a1")));
+        assert!(parse("(?P<1a>x)").is_err());
+        assert!(parse("(?P<>x)").is_err());
+        assert!(parse("(?P<a-b>x)").is_err());
+        assert_eq!(named("n").to_string(), "(?P<n>x)");
+    }
+
+    #[test]
+    fn duplicate_group_name() {
+        assert_eq!(
+            parse("(?P<x>a)(?P<x>b)"),
+            Err(ParseError::DuplicateGroupName("x".to_string()))
+        );
+        assert_eq!(
+            parse("(?P<x>a|(?<x>b))"),
+            Err(ParseError::DuplicateGroupName("x".to_string()))
+        );
+        assert!(parse("(?P<x>a)(?P<y>b)(c)(d)").is_ok());
+    }
+
+    #[test]
+    fn parse_literal_run() {
+        let pattern = "abcdefghij".repeat(1000);
+        let ast = parse(&pattern).unwrap();
+        assert_eq!(ast, Ast::Literals(Literals::new(pattern.clone())));
+        assert_eq!(crate::analysis::node_count(&ast), 1);
+        let ast = parse(&format!("x*{}y+", pattern)).unwrap();
+        assert_eq!(crate::analysis::node_count(&ast), 6);
+        assert_eq!(
+            parse("ab?c"),
+            Ok(Ast::Concatenation(Concatenation::new(vec![
+                Ast::Literal(Literal::new('a')),
+                Ast::Repetition(Repetition::new(
+                    Ast::Literal(Literal::new('b')),
+                    Quantifier::ZeroOrOne
+                )),
+                Ast::Literal(Literal::new('c')),
+            ])))
+        );
+    }
+
+    #[test]
+    fn nest_limit() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse(&nested(10_000)), Err(ParseError::TooDeep));
+        assert_eq!(parse(&nested(101)), Err(ParseError::TooDeep));
+        assert!(parse(&nested(100)).is_ok());
+        assert_eq!(parse_with_limit(&nested(3), 2), Err(ParseError::TooDeep));
+        assert!(parse_with_limit(&nested(2), 2).is_ok());
+        assert!(parse_with_limit("(a)(b)(c)", 1).is_ok());
+        assert_eq!(parse_with_limit("(a)((b))", 1), Err(ParseError::TooDeep));
+    }
+
+    #[test]
+    fn constructors() {
+        let ast = Ast::Repetition(Repetition::new(
+            Ast::Literal(Literal::new('a')),
+            Quantifier::OneOrMore,
+        ));
+        assert_eq!(parse("a+"), Ok(ast));
+        let ast = Ast::Concatenation(Concatenation::new(vec![
+            Ast::Group(Group::new(Ast::Alternative(Alternative::new(vec![
+                Ast::PerlClass(PerlClass::new(PerlClassKind::Digit, false)),
+                Ast::Bracket(Bracket::new(vec![BracketExpr::Range('a', 'f')], true)),
+            ])))),
+            Ast::Group(Group::with_kind(
+                Ast::Wildcard(Wildcard),
+                GroupKind::NonCapturing(Flags::new(Some(true), None, Some(false), None)),
+            )),
+        ]));
+        assert_eq!(parse(r"(\d|[^a-f])(?i-s:.)"), Ok(ast));
+    }
+
+    #[test]
+    fn from_str() {
+        let ast: Ast = "a|b".parse().unwrap();
+        assert_eq!(ast, parse("a|b").unwrap());
+        assert_eq!("a|b)".parse::<Ast>(), Err(ParseError::UnopenedGroup(3)));
+        assert_eq!("(a".parse::<Ast>(), Err(ParseError::UnbalancedGroup(0)));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for pattern in &[
+            "abc",
+            "a|b|cd",
+            "(a|b)*c",
+            "x(?:ab)+y",
+            r"a\.b\*\(\)\[\]\{\}\|\?\+\^\$\\",
+            r"\n\t\e",
+            "[^a-z0-9_]",
+            "[-a]",
+            "[a-]",
+            r"[\--z]",
+            r"[!-\-]",
+            r"[\]\[\^\\]",
+            "[[:alpha:][:space:]x]",
+            "a{2}b{3,}c{4,5}d?",
+            r"^\Aa$\z\Z",
+            r"\d\D\s\S\w\W.",
+            "(?i)a(?-i)b(?im-s)",
+            "(?s:a.)(?:b)(?i-m:c)",
+            "(?:)|a(?:)*",
+        ] {
+            let ast = parse(pattern).unwrap();
+            assert_eq!(ast.to_string(), *pattern);
+            assert_eq!(parse(&ast.to_string()), Ok(ast));
+        }
+    }
+
+    #[test]
+    fn display_adds_groups() {
+        let ast = Ast::Repetition(Repetition {
+            inner: Box::new(parse("ab").unwrap()),
+            quantifier: Quantifier::ZeroOrMore,
+        });
+        assert_eq!(ast.to_string(), "(?:ab)*");
+        let ast = Ast::Concatenation(Concatenation {
+            items: vec![parse("a|b").unwrap(), parse("c").unwrap()],
+        });
+        assert_eq!(ast.to_string(), "(?:a|b)c");
+    }
+
+    #[test]
+    fn parse_whole_input() {
+        assert_eq!(parse("a"), Ok(Ast::Literal(Literal { value: 'a' })));
+        assert_eq!(parse("ab)"), Err(ParseError::UnopenedGroup(2)));
+        assert_eq!(parse("ab{"), Err(ParseError::Syntax(2)));
+        assert_eq!(parse(""), Err(ParseError::Syntax(0)));
+    }
 }
@@ -0,0 +1,368 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ast::{Bracket, BracketExpr, Class, PerlClass, PerlClassKind};
+use crate::unicode_tables;
+
+/// Set of characters stored as sorted, non-overlapping inclusive ranges.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    pub fn new() -> CharClass {
+        CharClass { ranges: Vec::new() }
+    }
+
+    pub fn from_char(c: char) -> CharClass {
+        CharClass {
+            ranges: vec![(c, c)],
+        }
+    }
+
+    pub fn from_range(a: char, b: char) -> CharClass {
+        let mut class = CharClass::new();
+        class.push(a, b);
+        class
+    }
+
+    /// Characters the bracket matches, with named classes in Unicode mode if
+    /// `unicode`.
+    pub fn from_bracket(bracket: &Bracket, unicode: bool) -> CharClass {
+        let mut class = CharClass::from_exprs(bracket.exprs(), unicode);
+        if bracket.negated() {
+            class.negate();
+        }
+        class
+    }
+
+    /// Characters the class matches, in Unicode mode if `unicode`.
+    pub fn from_perl(perl: &PerlClass, unicode: bool) -> CharClass {
+        let mut class = if unicode {
+            unicode_perl(perl.kind())
+        } else {
+            CharClass::from_static(perl_ranges(perl.kind()))
+        };
+        if perl.negated() {
+            class.negate();
+        }
+        class
+    }
+
+    /// Union of the bracket expressions, ignoring negation, with named classes
+    /// in Unicode mode if `unicode`. Unicode properties aren't supported yet
+    /// and contribute nothing.
+    pub fn from_exprs(exprs: &[BracketExpr], unicode: bool) -> CharClass {
+        let mut class = CharClass::new();
+        for expr in exprs {
+            match expr {
+                BracketExpr::Char(c) => class.push(*c, *c),
+                BracketExpr::Range(a, b) => class.push(*a, *b),
+                BracketExpr::Class(c, negated) => {
+                    let mut named = CharClass {
+                        ranges: class_ranges(*c, unicode),
+                    };
+                    if *negated {
+                        named.negate();
+                    }
+                    class.union(&named);
+                }
+                BracketExpr::UnicodeProperty { .. } => {}
+            }
+        }
+        class
+    }
+
+    fn from_static(ranges: &[(char, char)]) -> CharClass {
+        CharClass {
+            ranges: ranges.to_vec(),
+        }
+    }
+
+    /// Everything except `\n`.
+    pub fn wildcard() -> CharClass {
+        CharClass {
+            ranges: vec![('\0', '\t'), ('\x0b', char::MAX)],
+        }
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(a, b)| {
+                if b < c {
+                    core::cmp::Ordering::Less
+                } else if a > c {
+                    core::cmp::Ordering::Greater
+                } else {
+                    core::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn push(&mut self, a: char, b: char) {
+        if a > b {
+            return;
+        }
+        let at = self.ranges.partition_point(|&(x, _)| x < a);
+        self.ranges.insert(at, (a, b));
+        self.canonicalize();
+    }
+
+    pub fn union(&mut self, other: &CharClass) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.ranges.sort_unstable();
+        self.canonicalize();
+    }
+
+    /// Adds the simple case variants of every character in the class.
+    pub fn case_fold(&mut self) {
+        let mut folded = Vec::new();
+        for &(a, b) in &self.ranges {
+            for c in (a..=b).filter(|c| c.is_alphabetic()) {
+                let lower = single(c.to_lowercase());
+                let upper = single(c.to_uppercase());
+                folded.extend(lower);
+                folded.extend(upper);
+                folded.extend(lower.and_then(|c| single(c.to_uppercase())));
+                folded.extend(upper.and_then(|c| single(c.to_lowercase())));
+            }
+        }
+        self.ranges.extend(folded.into_iter().map(|c| (c, c)));
+        self.ranges.sort_unstable();
+        self.canonicalize();
+    }
+
+    pub fn negate(&mut self) {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next = Some('\0');
+        for &(a, b) in &self.ranges {
+            if let Some(n) = next {
+                if n < a {
+                    ranges.push((n, prev_char(a)));
+                }
+            }
+            next = next_char(b);
+        }
+        if let Some(n) = next {
+            ranges.push((n, char::MAX));
+        }
+        self.ranges = ranges;
+    }
+
+    /// Merges overlapping and adjacent ranges, assuming they are sorted by start.
+    fn canonicalize(&mut self) {
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(a, b) in &self.ranges {
+            if let Some(last) = merged.last_mut() {
+                if next_char(last.1).is_none_or(|n| n >= a) {
+                    last.1 = last.1.max(b);
+                    continue;
+                }
+            }
+            merged.push((a, b));
+        }
+        self.ranges = merged;
+    }
+}
+
+/// Returns the only item of `iter`, if it has exactly one.
+fn single<I: Iterator<Item = char>>(mut iter: I) -> Option<char> {
+    let c = iter.next()?;
+    match iter.next() {
+        Some(_) => None,
+        None => Some(c),
+    }
+}
+
+pub(crate) fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        char::MAX => None,
+        c => core::char::from_u32(c as u32 + 1),
+    }
+}
+
+pub(crate) fn prev_char(c: char) -> char {
+    match c {
+        '\u{e000}' => '\u{d7ff}',
+        c => core::char::from_u32(c as u32 - 1).unwrap(),
+    }
+}
+
+/// Ranges of the characters in the named class, sorted and non-overlapping,
+/// for its Unicode variant if `unicode`.
+pub fn class_ranges(class: Class, unicode: bool) -> Vec<(char, char)> {
+    match (unicode_class(&class), unicode) {
+        (Some(class), true) => class.ranges,
+        _ => ascii_ranges(&class).to_vec(),
+    }
+}
+
+fn ascii_ranges(class: &Class) -> &'static [(char, char)] {
+    use Class::*;
+    match class {
+        Alnum => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+        Alpha => &[('A', 'Z'), ('a', 'z')],
+        Blank => &[('\t', '\t'), (' ', ' ')],
+        Cntrl => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+        Digit => &[('0', '9')],
+        Graph => &[('!', '~')],
+        Lower => &[('a', 'z')],
+        Print => &[(' ', '~')],
+        Punct => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        Space => &[('\t', '\r'), (' ', ' ')],
+        Upper => &[('A', 'Z')],
+        Xdigit => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+    }
+}
+
+/// Unicode variant of a named class, or `None` if it's the same as the ASCII
+/// one. Digits are the decimal ones only, so `½` and `²` aren't among them.
+fn unicode_class(class: &Class) -> Option<CharClass> {
+    use Class::*;
+    let table = match class {
+        Alnum => {
+            let mut alnum = CharClass::from_static(unicode_tables::ALPHABETIC);
+            alnum.union(&CharClass::from_static(unicode_tables::DECIMAL_NUMBER));
+            return Some(alnum);
+        }
+        Alpha => unicode_tables::ALPHABETIC,
+        Cntrl => unicode_tables::CONTROL,
+        Digit => unicode_tables::DECIMAL_NUMBER,
+        Lower => unicode_tables::LOWERCASE,
+        Space => unicode_tables::WHITE_SPACE,
+        Upper => unicode_tables::UPPERCASE,
+        Blank | Graph | Print | Punct | Xdigit => return None,
+    };
+    Some(CharClass::from_static(table))
+}
+
+fn unicode_perl(kind: PerlClassKind) -> CharClass {
+    match kind {
+        PerlClassKind::Digit => CharClass::from_static(unicode_tables::DECIMAL_NUMBER),
+        PerlClassKind::Space => CharClass::from_static(unicode_tables::WHITE_SPACE),
+        PerlClassKind::Word => {
+            let mut word = unicode_class(&Class::Alnum).unwrap();
+            word.push('_', '_');
+            word
+        }
+    }
+}
+
+fn perl_ranges(kind: PerlClassKind) -> &'static [(char, char)] {
+    match kind {
+        PerlClassKind::Digit => ascii_ranges(&Class::Digit),
+        PerlClassKind::Space => ascii_ranges(&Class::Space),
+        PerlClassKind::Word => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_merges_ranges() {
+        let mut class = CharClass::new();
+        class.push('d', 'f');
+        class.push('a', 'c');
+        class.push('x', 'z');
+        class.push('e', 'h');
+        assert_eq!(class.ranges(), &[('a', 'h'), ('x', 'z')]);
+    }
+
+    #[test]
+    fn negate() {
+        let mut class = CharClass::from_char('b');
+        class.negate();
+        assert_eq!(class.ranges(), &[('\0', 'a'), ('c', char::MAX)]);
+        assert!(!class.contains('b'));
+        class.negate();
+        assert_eq!(class, CharClass::from_char('b'));
+    }
+
+    #[test]
+    fn case_fold() {
+        let mut class = CharClass::from_range('a', 'c');
+        class.push('X', 'X');
+        class.push('1', '1');
+        class.case_fold();
+        assert_eq!(
+            class.ranges(),
+            &[('1', '1'), ('A', 'C'), ('X', 'X'), ('a', 'c'), ('x', 'x')]
+        );
+        let mut class = CharClass::from_char('Ł');
+        class.case_fold();
+        assert!(class.contains('ł'));
+        let mut class = CharClass::from_char('\u{212a}');
+        class.case_fold();
+        assert!(class.contains('k'));
+        assert!(class.contains('K'));
+    }
+
+    #[test]
+    fn negated_named_class() {
+        let bracket = Bracket::new(
+            vec![
+                BracketExpr::Class(Class::Digit, true),
+                BracketExpr::Char('5'),
+            ],
+            false,
+        );
+        let class = CharClass::from_bracket(&bracket, false);
+        assert!(class.contains('a'));
+        assert!(class.contains('5'));
+        assert!(!class.contains('4'));
+    }
+
+    #[test]
+    fn unicode_classes() {
+        let digit = PerlClass::new(PerlClassKind::Digit, false);
+        assert!(!CharClass::from_perl(&digit, false).contains('٣'));
+        assert!(CharClass::from_perl(&digit, true).contains('٣'));
+        let alpha = [BracketExpr::Class(Class::Alpha, true)];
+        assert!(CharClass::from_exprs(&alpha, false).contains('ż'));
+        assert!(!CharClass::from_exprs(&alpha, true).contains('ż'));
+        assert!(!CharClass::from_perl(&digit, true).contains('½'));
+        let xdigit = [BracketExpr::Class(Class::Xdigit, false)];
+        assert_eq!(
+            CharClass::from_exprs(&xdigit, true),
+            CharClass::from_exprs(&xdigit, false)
+        );
+    }
+
+    #[test]
+    fn named_class_ranges() {
+        assert_eq!(class_ranges(Class::Digit, false), [('0', '9')]);
+        let unicode = CharClass {
+            ranges: class_ranges(Class::Digit, true),
+        };
+        assert!(unicode.contains('5') && unicode.contains('٣'));
+        assert!(!unicode.contains('½') && !unicode.contains('²'));
+        assert!(class_ranges(Class::Digit, false)
+            .iter()
+            .all(|&(a, b)| unicode.contains(a) && unicode.contains(b)));
+        assert_eq!(
+            class_ranges(Class::Punct, true),
+            class_ranges(Class::Punct, false)
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let class = CharClass::wildcard();
+        assert!(class.contains('a'));
+        assert!(class.contains('\0'));
+        assert!(!class.contains('\n'));
+    }
+}
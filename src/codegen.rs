@@ -0,0 +1,226 @@
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::dfa::{determinize, Dfa, DfaState};
+use crate::nfa::{compile_with, CompileError, Config};
+
+/// Emits the source of a standalone Rust function, `is_match(haystack: &str)
+/// -> bool`, that finds the pattern like `Regex::is_match` does, by running a
+/// DFA unrolled into `match` expressions.
+///
+/// There's no size limit, so patterns with large DFAs yield large functions.
+/// Fails only for what can't be compiled at all, like look-around.
+pub fn codegen(ast: &Ast) -> Result<String, CompileError> {
+    let config = Config {
+        size_limit: usize::MAX,
+        ..Config::default()
+    };
+    let dfa = determinize(&compile_with(ast, config)?);
+    let mut out = String::new();
+    write_matcher(&mut out, &dfa).unwrap();
+    Ok(out)
+}
+
+fn write_matcher(out: &mut String, dfa: &Dfa) -> std::fmt::Result {
+    writeln!(
+        out,
+        "#[allow(clippy::all, unreachable_code, unreachable_patterns, unused_mut, unused_variables)]"
+    )?;
+    writeln!(out, "pub fn is_match(haystack: &str) -> bool {{")?;
+    writeln!(out, "    let mut state: usize = {};", dfa.start())?;
+    writeln!(out, "    for (pos, c) in haystack.char_indices() {{")?;
+    writeln!(
+        out,
+        "        let last = pos + c.len_utf8() == haystack.len();"
+    )?;
+    writeln!(out, "        state = match state {{")?;
+    let live = live_states(dfa);
+    for (id, state) in dfa.states().iter().enumerate() {
+        writeln!(out, "            {} => {{", id)?;
+        write_state(out, dfa, state, live[id])?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "            _ => unreachable!(),")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "    }}")?;
+    let at_end: Vec<_> = dfa
+        .states()
+        .iter()
+        .enumerate()
+        .filter(|(_, state)| state.is_accepting_at_end())
+        .map(|(id, _)| id.to_string())
+        .collect();
+    if at_end.is_empty() {
+        writeln!(out, "    false")?;
+    } else {
+        writeln!(out, "    matches!(state, {})", at_end.join(" | "))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Which states can still lead to a match.
+fn live_states(dfa: &Dfa) -> Vec<bool> {
+    let states = dfa.states();
+    let mut live: Vec<bool> = states
+        .iter()
+        .map(|state| {
+            state.is_accepting()
+                || state.is_accepting_before_newline()
+                || state.is_accepting_before_final_newline()
+                || state.is_accepting_at_end()
+        })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, state) in states.iter().enumerate() {
+            if !live[id] && state.next().iter().any(|&next| live[next]) {
+                live[id] = true;
+                changed = true;
+            }
+        }
+    }
+    live
+}
+
+/// Writes the body of a state's arm, which accepts or picks the next state
+/// for `c`.
+fn write_state(out: &mut String, dfa: &Dfa, state: &DfaState, live: bool) -> std::fmt::Result {
+    let indent = "                ";
+    if state.is_accepting() {
+        return writeln!(out, "{}return true;", indent);
+    }
+    if !live {
+        return writeln!(out, "{}return false;", indent);
+    }
+    if state.is_accepting_before_newline() {
+        writeln!(out, "{}if c == '\\n' && !last {{ return true; }}", indent)?;
+    }
+    if state.is_accepting_before_final_newline() {
+        writeln!(out, "{}if c == '\\n' && last {{ return true; }}", indent)?;
+    }
+    let alphabet = dfa.alphabet();
+    let next = state.next();
+    // Adjacent classes going to the same state, merged into one range each.
+    let mut arms: Vec<(usize, Vec<(char, char)>)> = Vec::new();
+    let mut previous: Option<(usize, char, char)> = None;
+    for (class, &target) in next.iter().enumerate().take(alphabet.len()) {
+        let (a, b) = alphabet.range(class);
+        previous = match previous {
+            Some((t, start, _)) if t == target => Some((t, start, b)),
+            Some((t, start, end)) => {
+                push_range(&mut arms, t, start, end);
+                Some((target, a, b))
+            }
+            None => Some((target, a, b)),
+        };
+    }
+    if let Some((t, start, end)) = previous {
+        push_range(&mut arms, t, start, end);
+    }
+    // The most common target goes last, as the catch-all arm.
+    let fallback = arms
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, ranges))| ranges.len())
+        .map(|(i, _)| i)
+        .unwrap();
+    let (fallback, _) = arms.remove(fallback);
+    writeln!(out, "{}match c {{", indent)?;
+    let final_newline = next[alphabet.len()];
+    if final_newline != next[alphabet.class_of('\n')] {
+        writeln!(out, "{}    '\\n' if last => {},", indent, final_newline)?;
+    }
+    for (target, ranges) in arms {
+        let patterns: Vec<_> = ranges.iter().map(|&(a, b)| pattern(a, b)).collect();
+        writeln!(out, "{}    {} => {},", indent, patterns.join(" | "), target)?;
+    }
+    writeln!(out, "{}    _ => {},", indent, fallback)?;
+    writeln!(out, "{}}}", indent)
+}
+
+fn push_range(arms: &mut Vec<(usize, Vec<(char, char)>)>, target: usize, a: char, b: char) {
+    match arms.iter_mut().find(|(t, _)| *t == target) {
+        Some((_, ranges)) => ranges.push((a, b)),
+        None => arms.push((target, vec![(a, b)])),
+    }
+}
+
+fn pattern(a: char, b: char) -> String {
+    if a == b {
+        format!("{:?}", a)
+    } else {
+        format!("{:?}..={:?}", a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    #[test]
+    fn codegen_concatenation() {
+        let code = codegen(&parse("ab*c").unwrap()).unwrap();
+        assert!(code.starts_with("#[allow("), "{}", code);
+        assert!(code.contains("pub fn is_match(haystack: &str) -> bool {"));
+        assert!(code.contains("for (pos, c) in haystack.char_indices() {"));
+        assert!(code.contains("'a' => "), "{}", code);
+        assert!(code.contains("'b' => "), "{}", code);
+        assert!(code.contains("'c' => "), "{}", code);
+        assert!(code.contains("return true;"), "{}", code);
+        assert_eq!(code.matches('{').count(), code.matches('}').count());
+    }
+
+    #[test]
+    fn codegen_anchors_and_wildcard() {
+        let code = codegen(&parse("^a.$").unwrap()).unwrap();
+        assert!(code.contains("return false;"), "{}", code);
+        assert!(code.contains("matches!(state, 4)"), "{}", code);
+        let code = codegen(&parse(r"a\Z").unwrap()).unwrap();
+        assert!(
+            code.contains("if c == '\\n' && last { return true; }"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn codegen_compiles_and_matches() {
+        let patterns = ["ab*c", "^a.$", r"a\Z", "(?m)^x$", "[a-c]+d|e", "a", "^$"];
+        let haystacks = [
+            "", "a", "ac", "abbc", "a\n", "xa\n", "x\nx\n", "ccd", "e", "\n",
+        ];
+        let dir = std::env::temp_dir().join(format!("regex-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let mut source = codegen(&parse(pattern).unwrap()).unwrap();
+            source.push_str(
+                "fn main() {\n    for arg in std::env::args().skip(1) {\n        \
+                 println!(\"{}\", is_match(&arg));\n    }\n}\n",
+            );
+            let file = dir.join(format!("matcher{}.rs", i));
+            let binary = dir.join(format!("matcher{}", i));
+            std::fs::write(&file, source).unwrap();
+            let status = std::process::Command::new("rustc")
+                .args(["--edition", "2018", "-D", "warnings", "-o"])
+                .arg(&binary)
+                .arg(&file)
+                .status()
+                .unwrap();
+            assert!(status.success(), "{}", pattern);
+            let output = std::process::Command::new(&binary)
+                .args(haystacks)
+                .output()
+                .unwrap();
+            let output = String::from_utf8(output.stdout).unwrap();
+            let regex = crate::Regex::new(pattern).unwrap();
+            for (haystack, line) in haystacks.iter().zip(output.lines()) {
+                let expected = regex.is_match(haystack).to_string();
+                assert_eq!(line, expected, "{} on {:?}", pattern, haystack);
+            }
+            assert_eq!(output.lines().count(), haystacks.len());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
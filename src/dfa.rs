@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+
+use crate::class::{next_char, prev_char};
+use crate::nfa::{Look, Nfa, State};
+
+/// Deterministic automaton built from an `Nfa` via subset construction.
+///
+/// Unless the NFA is anchored, every state also contains the NFA start state,
+/// so a match may begin at any position.
+#[derive(Clone, Debug)]
+pub struct Dfa {
+    alphabet: Alphabet,
+    states: Vec<DfaState>,
+    start: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct DfaState {
+    nfa_states: Vec<usize>,
+    context: Context,
+    next: Vec<usize>,
+    accept: Accept,
+}
+
+impl DfaState {
+    /// NFA states this state is made of.
+    pub fn nfa_states(&self) -> &[usize] {
+        &self.nfa_states
+    }
+
+    /// Target state for each alphabet class, followed by the target on a
+    /// newline ending the haystack.
+    pub fn next(&self) -> &[usize] {
+        &self.next
+    }
+
+    pub fn is_accepting(&self) -> bool {
+        self.accept.now
+    }
+
+    /// Whether the state accepts at the end of the haystack, where end
+    /// assertions hold.
+    pub fn is_accepting_at_end(&self) -> bool {
+        self.accept.at_end
+    }
+
+    /// Whether the state accepts before a newline that doesn't end the
+    /// haystack, where `$` holds in multi-line mode.
+    pub fn is_accepting_before_newline(&self) -> bool {
+        self.accept.before_newline
+    }
+
+    /// Whether the state accepts before a newline ending the haystack, where
+    /// `$` and `\Z` hold.
+    pub fn is_accepting_before_final_newline(&self) -> bool {
+        self.accept.before_final_newline
+    }
+
+    /// Whether no match can be reached from this state anymore.
+    pub fn is_dead(&self) -> bool {
+        self.nfa_states.is_empty()
+    }
+}
+
+impl Dfa {
+    pub fn states(&self) -> &[DfaState] {
+        &self.states
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        let mut id = self.start;
+        for (pos, c) in haystack.char_indices() {
+            let last = pos + c.len_utf8() == haystack.len();
+            let state = &self.states[id];
+            if state.accept.before(c, last) {
+                return true;
+            }
+            id = state.next[self.alphabet.column(c, last)];
+        }
+        self.states[id].accept.at_end
+    }
+}
+
+pub fn determinize(nfa: &Nfa) -> Dfa {
+    let mut builder = Builder::new(nfa);
+    let start = builder.start(nfa);
+    let mut id = 0;
+    while id < builder.states.len() {
+        for column in 0..=builder.alphabet.len() {
+            let next = builder.transition(nfa, id, column);
+            builder.states[id].next[column] = next;
+        }
+        id += 1;
+    }
+    Dfa {
+        alphabet: builder.alphabet,
+        states: builder.states,
+        start,
+    }
+}
+
+/// Transition that hasn't been computed yet.
+const UNKNOWN: usize = usize::MAX;
+
+/// DFA whose states are computed on demand while matching.
+///
+/// At most `limit` states are cached. Once a search needs more, the cache is
+/// cleared and the search gives up, leaving it to the NFA.
+#[derive(Clone, Debug)]
+pub struct LazyDfa {
+    builder: Builder,
+    start: usize,
+    limit: usize,
+}
+
+impl LazyDfa {
+    pub fn new(nfa: &Nfa, limit: usize) -> LazyDfa {
+        let mut builder = Builder::new(nfa);
+        let start = builder.start(nfa);
+        LazyDfa {
+            builder,
+            start,
+            limit,
+        }
+    }
+
+    /// Number of currently cached states.
+    pub fn len(&self) -> usize {
+        self.builder.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.builder.states.is_empty()
+    }
+
+    /// Returns `None` if the search exceeded the cache limit.
+    pub fn is_match(&mut self, nfa: &Nfa, haystack: &str) -> Option<bool> {
+        self.shortest_match(nfa, haystack).map(|end| end.is_some())
+    }
+
+    /// Returns the end of the first match to complete, or `None` if the search
+    /// exceeded the cache limit.
+    pub fn shortest_match(&mut self, nfa: &Nfa, haystack: &str) -> Option<Option<usize>> {
+        let mut id = self.start;
+        for (pos, c) in haystack.char_indices() {
+            let last = pos + c.len_utf8() == haystack.len();
+            if self.builder.states[id].accept.before(c, last) {
+                return Some(Some(pos));
+            }
+            let column = self.builder.alphabet.column(c, last);
+            let mut next = self.builder.states[id].next[column];
+            if next == UNKNOWN {
+                if self.builder.states.len() >= self.limit {
+                    self.clear(nfa);
+                    return None;
+                }
+                next = self.builder.transition(nfa, id, column);
+                self.builder.states[id].next[column] = next;
+            }
+            id = next;
+        }
+        let accept = self.builder.states[id].accept.at_end;
+        Some(if accept { Some(haystack.len()) } else { None })
+    }
+
+    fn clear(&mut self, nfa: &Nfa) {
+        *self = LazyDfa::new(nfa, self.limit);
+    }
+}
+
+/// Partition of all characters into classes that no NFA state distinguishes.
+///
+/// `\n` always gets a class of its own, as line assertions depend on it.
+#[derive(Clone, Debug)]
+pub struct Alphabet {
+    starts: Vec<char>,
+}
+
+impl Alphabet {
+    fn new(nfa: &Nfa) -> Alphabet {
+        let mut starts = vec!['\0', '\n', '\x0b'];
+        for state in nfa.states() {
+            if let State::Class(class, _) = state {
+                for &(a, b) in class.ranges() {
+                    starts.push(a);
+                    starts.extend(next_char(b));
+                }
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        Alphabet { starts }
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    pub fn class_of(&self, c: char) -> usize {
+        self.starts.partition_point(|&start| start <= c) - 1
+    }
+
+    /// Inclusive character range covered by a class.
+    pub fn range(&self, class: usize) -> (char, char) {
+        let end = self
+            .starts
+            .get(class + 1)
+            .map_or(char::MAX, |&next| prev_char(next));
+        (self.starts[class], end)
+    }
+
+    /// Index of the transition taken on `c`, where a final newline gets an
+    /// extra column past the classes.
+    fn column(&self, c: char, last: bool) -> usize {
+        if c == '\n' && last {
+            self.len()
+        } else {
+            self.class_of(c)
+        }
+    }
+}
+
+/// Whether a state accepts, depending on which assertions hold.
+#[derive(Copy, Clone, Debug, Default)]
+struct Accept {
+    now: bool,
+    before_newline: bool,
+    before_final_newline: bool,
+    at_end: bool,
+}
+
+impl Accept {
+    /// Whether the state accepts right before consuming `c`.
+    fn before(&self, c: char, last: bool) -> bool {
+        self.now
+            || (c == '\n' && !last && self.before_newline)
+            || (c == '\n' && last && self.before_final_newline)
+    }
+}
+
+/// What precedes the current position, which decides start assertions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Context {
+    StartOfText,
+    StartOfLine,
+    Mid,
+}
+
+impl Context {
+    fn holds(self, look: Look) -> bool {
+        match look {
+            Look::StartOfText => self == Context::StartOfText,
+            Look::StartOfLine => self != Context::Mid,
+            _ => false,
+        }
+    }
+}
+
+/// Subset-construction machinery shared by eager and lazy determinization.
+#[derive(Clone, Debug)]
+struct Builder {
+    alphabet: Alphabet,
+    states: Vec<DfaState>,
+    ids: HashMap<(Context, Vec<usize>), usize>,
+    restart: HashMap<Context, Vec<usize>>,
+}
+
+impl Builder {
+    fn new(nfa: &Nfa) -> Builder {
+        Builder {
+            alphabet: Alphabet::new(nfa),
+            states: Vec::new(),
+            ids: HashMap::new(),
+            restart: [Context::StartOfLine, Context::Mid]
+                .iter()
+                .map(|&ctx| {
+                    let roots: &[usize] = if nfa.is_anchored() {
+                        &[]
+                    } else {
+                        &[nfa.start()]
+                    };
+                    (ctx, closure(nfa, roots, ctx, |_| false))
+                })
+                .collect(),
+        }
+    }
+
+    fn start(&mut self, nfa: &Nfa) -> usize {
+        let set = closure(nfa, &[nfa.start()], Context::StartOfText, |_| false);
+        self.add(nfa, Context::StartOfText, set)
+    }
+
+    /// Computes the target of `id` for a column, adding the state if it's new.
+    fn transition(&mut self, nfa: &Nfa, id: usize, column: usize) -> usize {
+        let last = column == self.alphabet.len();
+        let c = if last {
+            '\n'
+        } else {
+            self.alphabet.range(column).0
+        };
+        let state = &self.states[id];
+        // Knowing the next character resolves pending end assertions.
+        let set = closure(nfa, &state.nfa_states, state.context, |look| match look {
+            Look::EndOfLine => c == '\n',
+            Look::EndOrBeforeFinalNewline => last,
+            _ => false,
+        });
+        let ctx = if c == '\n' {
+            Context::StartOfLine
+        } else {
+            Context::Mid
+        };
+        let mut roots = self.restart[&ctx].clone();
+        for &s in &set {
+            if let State::Class(cls, next) = &nfa.states()[s] {
+                if cls.contains(c) {
+                    roots.push(*next);
+                }
+            }
+        }
+        let set = closure(nfa, &roots, ctx, |_| false);
+        self.add(nfa, ctx, set)
+    }
+
+    fn add(&mut self, nfa: &Nfa, ctx: Context, set: Vec<usize>) -> usize {
+        let key = (ctx, set);
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        let reaches_match = |holds: fn(Look) -> bool| {
+            closure(nfa, &key.1, ctx, holds)
+                .iter()
+                .any(|&s| matches!(nfa.states()[s], State::Match(_)))
+        };
+        let accept = Accept {
+            now: reaches_match(|_| false),
+            before_newline: reaches_match(|look| look == Look::EndOfLine),
+            before_final_newline: reaches_match(|look| {
+                look == Look::EndOfLine || look == Look::EndOrBeforeFinalNewline
+            }),
+            at_end: reaches_match(|_| true),
+        };
+        let id = self.states.len();
+        self.states.push(DfaState {
+            nfa_states: key.1.clone(),
+            context: ctx,
+            next: vec![UNKNOWN; self.alphabet.len() + 1],
+            accept,
+        });
+        self.ids.insert(key, id);
+        id
+    }
+}
+
+/// Follows epsilon transitions from `roots`, keeping consuming states, `Match`
+/// and assertions that can't be decided yet. Start assertions are decided by
+/// `ctx`, end assertions by `holds`.
+fn closure<F: Fn(Look) -> bool>(nfa: &Nfa, roots: &[usize], ctx: Context, holds: F) -> Vec<usize> {
+    let states = nfa.states();
+    let mut seen = vec![false; states.len()];
+    let mut set = Vec::new();
+    let mut stack: Vec<usize> = roots.iter().rev().cloned().collect();
+    while let Some(id) = stack.pop() {
+        if seen[id] {
+            continue;
+        }
+        seen[id] = true;
+        match states[id] {
+            State::Split(a, b) => {
+                stack.push(b);
+                stack.push(a);
+            }
+            State::Epsilon(to) | State::Save(_, to) => stack.push(to),
+            State::Assert(look @ Look::StartOfText, to)
+            | State::Assert(look @ Look::StartOfLine, to) => {
+                if ctx.holds(look) {
+                    stack.push(to);
+                }
+            }
+            State::Assert(look, to) => {
+                if holds(look) {
+                    stack.push(to);
+                } else {
+                    set.push(id);
+                }
+            }
+            State::Class(..) | State::Match(_) => set.push(id),
+        }
+    }
+    set.sort_unstable();
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+    use crate::nfa::{compile, compile_with, Config};
+
+    fn assert_equivalent(pattern: &str, haystacks: &[&str]) {
+        let nfa = compile(&parse(pattern).unwrap()).unwrap();
+        let dfa = determinize(&nfa);
+        for &haystack in haystacks {
+            assert_eq!(
+                dfa.is_match(haystack),
+                nfa.search(haystack, 0).is_some(),
+                "{:?} on {:?}",
+                pattern,
+                haystack
+            );
+        }
+    }
+
+    const HAYSTACKS: &[&str] = &["", "a", "b", "ab", "abab", "ba", "xaby", "aaa", "a\n", "\n"];
+
+    #[test]
+    fn equivalent_to_nfa() {
+        for pattern in &["a", "ab", "a|b", "(ab)+", "a*b", "a{2,3}", "[^a]", ".b"] {
+            assert_equivalent(pattern, HAYSTACKS);
+        }
+    }
+
+    #[test]
+    fn equivalent_to_nfa_with_anchors() {
+        for pattern in &["^a", "a$", "^$", r"b\Z", r"\Aab\z", "^a|b$", "a*$"] {
+            assert_equivalent(pattern, HAYSTACKS);
+        }
+    }
+
+    #[test]
+    fn equivalent_to_nfa_multi_line() {
+        let haystacks = ["a\nb", "b\na", "a\n\nb", "\n", "ab\n", "a\n"];
+        for pattern in &[
+            "(?m)^b",
+            "(?m)a$",
+            "(?m)^$",
+            "(?m)$^",
+            "(?m)a$\n",
+            r"a\Z\n",
+            "(?m)a$|^b",
+            "(?ms)a.^b",
+        ] {
+            assert_equivalent(pattern, &haystacks);
+            assert_equivalent(pattern, HAYSTACKS);
+        }
+    }
+
+    #[test]
+    fn equivalent_to_nfa_anchored() {
+        let config = Config {
+            anchored: true,
+            ..Config::default()
+        };
+        for pattern in &["a", "ab|b", "a*b", "(?m)$"] {
+            let nfa = compile_with(&parse(pattern).unwrap(), config).unwrap();
+            let dfa = determinize(&nfa);
+            for &haystack in HAYSTACKS {
+                assert_eq!(
+                    dfa.is_match(haystack),
+                    nfa.search(haystack, 0).is_some(),
+                    "{:?} on {:?}",
+                    pattern,
+                    haystack
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_equivalent_to_nfa() {
+        let nfa = compile(&parse("a.{20}").unwrap()).unwrap();
+        let mut lazy = LazyDfa::new(&nfa, 1000);
+        let haystacks = ["a", "aaaaaaaaaaaaaaaaaaaa", "baaaaaaaaaaaaaaaaaaaaa", "xyz"];
+        for &haystack in &haystacks {
+            assert_eq!(
+                lazy.is_match(&nfa, haystack),
+                Some(nfa.search(haystack, 0).is_some())
+            );
+        }
+        assert!(lazy.len() < 100);
+    }
+
+    #[test]
+    fn lazy_shortest_match() {
+        let haystacks = ["aaa", "abc", "xyz", "", "b\n"];
+        for pattern in &["a+", "abc|b", "b$", "x*"] {
+            let nfa = compile(&parse(pattern).unwrap()).unwrap();
+            let mut lazy = LazyDfa::new(&nfa, 1000);
+            for &haystack in &haystacks {
+                assert_eq!(
+                    lazy.shortest_match(&nfa, haystack),
+                    Some(nfa.shortest(haystack, 0)),
+                    "{:?} on {:?}",
+                    pattern,
+                    haystack
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_gives_up_when_full() {
+        let nfa = compile(&parse("a.{20}").unwrap()).unwrap();
+        let mut lazy = LazyDfa::new(&nfa, 8);
+        let haystack = "abaabbbaaababbabababbbbaaabbbab";
+        assert_eq!(lazy.is_match(&nfa, haystack), None);
+        assert_eq!(lazy.len(), 1);
+        assert_eq!(lazy.is_match(&nfa, "b"), Some(false));
+    }
+
+    #[test]
+    fn alphabet() {
+        let nfa = compile(&parse("[a-c]|c").unwrap()).unwrap();
+        let alphabet = determinize(&nfa).alphabet;
+        assert_eq!(alphabet.class_of('a'), alphabet.class_of('b'));
+        assert_ne!(alphabet.class_of('a'), alphabet.class_of('c'));
+        assert_eq!(alphabet.class_of('\x0b'), alphabet.class_of('Z'));
+        assert_eq!(alphabet.range(alphabet.class_of('\n')), ('\n', '\n'));
+        assert_eq!(alphabet.range(alphabet.class_of('c')), ('c', 'c'));
+        assert_eq!(alphabet.range(alphabet.class_of('d')), ('d', char::MAX));
+    }
+
+    #[test]
+    fn states_are_shared() {
+        let nfa = compile(&parse("a*").unwrap()).unwrap();
+        assert!(determinize(&nfa).states().len() <= 3);
+    }
+}
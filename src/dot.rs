@@ -1,198 +1,566 @@
+use std::fmt::{self, Display, Write as _};
 use std::io::{self, Write};
+use std::str;
 
 use crate::ast;
+use crate::class::CharClass;
+use crate::dfa::Dfa;
+use crate::layout::{Edges, Layout};
+use crate::nfa::{Look, Nfa, State};
 
-use crate::visit::{Visitable, Visitor};
+/// Direction in which the graph is laid out.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum RankDir {
+    TopToBottom,
+    #[default]
+    LeftToRight,
+    RightToLeft,
+    BottomToTop,
+}
+
+impl Display for RankDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RankDir::TopToBottom => "TB",
+            RankDir::LeftToRight => "LR",
+            RankDir::RightToLeft => "RL",
+            RankDir::BottomToTop => "BT",
+        })
+    }
+}
+
+/// Adapts a `fmt::Write`, like `String`, to `io::Write`.
+pub struct FmtWriter<F>(F);
+
+impl<F: fmt::Write> Write for FmtWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s =
+            str::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.0.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 pub struct GraphvizCompiler<W> {
-    last: usize,
+    rankdir: RankDir,
+    highlight_accepting: bool,
+    compact_brackets: bool,
     output: W,
 }
 
+impl<F: fmt::Write> GraphvizCompiler<FmtWriter<F>> {
+    /// Renders into a `fmt::Write` instead of an `io::Write`.
+    pub fn from_fmt(output: F) -> GraphvizCompiler<FmtWriter<F>> {
+        GraphvizCompiler::new(FmtWriter(output))
+    }
+}
+
 impl<W: Write> GraphvizCompiler<W> {
     pub fn new(output: W) -> GraphvizCompiler<W> {
-        GraphvizCompiler { last: 0, output }
+        GraphvizCompiler {
+            rankdir: RankDir::default(),
+            highlight_accepting: false,
+            compact_brackets: false,
+            output,
+        }
+    }
+
+    pub fn with_rankdir(mut self, rankdir: RankDir) -> GraphvizCompiler<W> {
+        self.rankdir = rankdir;
+        self
     }
 
+    /// Fills the accepting state with a color.
+    pub fn highlight_accepting(mut self, yes: bool) -> GraphvizCompiler<W> {
+        self.highlight_accepting = yes;
+        self
+    }
+
+    /// Draws each bracket as a single edge labeled with all of its items,
+    /// instead of one edge per item.
+    pub fn compact_brackets(mut self, yes: bool) -> GraphvizCompiler<W> {
+        self.compact_brackets = yes;
+        self
+    }
+
+    /// Writes a graph for `ast`. Each call writes a separate graph, numbered
+    /// from 0.
     pub fn render(&mut self, ast: &ast::Ast) -> io::Result<()> {
-        writeln!(self.output, "digraph {{\nrankdir = LR;")?;
-        ast.accept(self)?;
-        for node in 0..self.last {
+        writeln!(self.output, "digraph {{\nrankdir = {};", self.rankdir)?;
+        let last = Layout::run(ast, &mut DotEdges(&mut self.output), self.compact_brackets)?;
+        for node in 0..last {
             writeln!(self.output, "{} [shape = circle];", node)?;
         }
-        writeln!(self.output, "{} [shape = doublecircle];\n}}", self.last)?;
+        let fill = if self.highlight_accepting {
+            ", style = filled, fillcolor = lightgreen"
+        } else {
+            ""
+        };
+        writeln!(self.output, "{} [shape = doublecircle{}];\n}}", last, fill)?;
         Ok(())
     }
 }
 
-impl<W: Write> Visitor<io::Result<()>> for GraphvizCompiler<W> {
-    fn visit(&mut self, node: &ast::Ast) -> io::Result<()> {
-        node.accept(self)
-    }
-
-    fn visit_literal(&mut self, node: &ast::Literal) -> io::Result<()> {
-        self.last += 1;
-        writeln!(
-            self.output,
-            "{} -> {} [label = {}];",
-            self.last - 1,
-            self.last,
-            node.value()
-        )
-    }
-
-    fn visit_wildcard(&mut self, _: &ast::Wildcard) -> io::Result<()> {
-        self.last += 1;
-        writeln!(
-            self.output,
-            "{} -> {} [label = ANY];",
-            self.last - 1,
-            self.last,
-        )
-    }
-
-    fn visit_bracket(&mut self, node: &ast::Bracket) -> io::Result<()> {
-        let start = self.last;
-        let negated = if node.negated() { "not " } else { "" };
-        for expr in node.exprs() {
-            writeln!(self.output, "{} -> {} [label = ε];", start, self.last + 1)?;
-            match expr {
-                ast::BracketExpr::Char(c) => {
-                    writeln!(
-                        self.output,
-                        "{} -> {} [label = \"{}{}\"];",
-                        self.last + 1,
-                        self.last + 2,
-                        negated,
-                        c
-                    )?;
-                }
-                ast::BracketExpr::Range(a, b) => {
-                    writeln!(
-                        self.output,
-                        "{} -> {} [label = \"{}{}-{}\"]",
-                        self.last + 1,
-                        self.last + 2,
-                        negated,
-                        a,
-                        b
-                    )?;
-                }
-                ast::BracketExpr::Class(class) => {
-                    use ast::Class::*;
-                    let trans = match class {
-                        Alnum => "alphanumeric",
-                        Alpha => "alpha",
-                        Blank => "blank",
-                        Cntrl => "control",
-                        Digit => "digit",
-                        Graph => "graph",
-                        Lower => "lowercase",
-                        Print => "printable",
-                        Punct => "punctuation",
-                        Space => "whitespace",
-                        Upper => "uppercase",
-                        Xdigit => "hexadecimal",
-                    };
-                    writeln!(
-                        self.output,
-                        "{} -> {} [label = \"{}{}\"];",
-                        self.last + 1,
-                        self.last + 2,
-                        negated,
-                        trans
-                    )?;
-                }
-            }
-            self.last += 2;
-        }
-        self.last += 1;
-        for id in ((start + 2)..self.last).step_by(2) {
-            writeln!(self.output, "{} -> {} [label = ε];", id, self.last)?;
+struct DotEdges<'w, W>(&'w mut W);
+
+impl<'w, W: Write> Edges for DotEdges<'w, W> {
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) -> io::Result<()> {
+        match label {
+            Some(label) => writeln!(self.0, "{} -> {} [label = {}];", from, to, Label(label)),
+            None => writeln!(self.0, "{} -> {} [label = ε];", from, to),
         }
-        Ok(())
     }
+}
 
-    fn visit_concatenation(&mut self, node: &ast::Concatenation) -> io::Result<()> {
-        for node in node.items() {
-            node.accept(self)?
+/// Edge label text, quoted and escaped for DOT. Control characters are shown
+/// in escape notation, like `\n`.
+struct Label<T>(T);
+
+impl<T: Display> Display for Label<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.to_string().chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                // Graphviz itself interprets `\n`, so escape the backslash.
+                '\n' => f.write_str("\\\\n")?,
+                '\r' => f.write_str("\\\\r")?,
+                '\t' => f.write_str("\\\\t")?,
+                c if c.is_control() => write!(f, "\\\\x{:02x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
         }
-        Ok(())
+        f.write_char('"')
     }
+}
 
-    fn visit_alternative(&mut self, node: &ast::Alternative) -> io::Result<()> {
-        let start = self.last;
-        let mut accepting = Vec::with_capacity(node.items().len());
-        for node in node.items() {
-            self.last += 1;
-            writeln!(self.output, "{} -> {} [label = ε];", start, self.last)?;
-            node.accept(self)?;
-            accepting.push(self.last);
-        }
-        self.last += 1;
-        for id in accepting {
-            writeln!(self.output, "{} -> {} [label = ε];", id, self.last)?;
+/// Draws the states and transitions of a compiled NFA, numbered by index.
+pub fn nfa_to_dot<W: Write>(nfa: &Nfa, mut output: W) -> io::Result<()> {
+    writeln!(output, "digraph {{\nrankdir = {};", RankDir::default())?;
+    for (id, state) in nfa.states().iter().enumerate() {
+        match state {
+            State::Class(class, to) => writeln!(
+                output,
+                "{} -> {} [label = {}];",
+                id,
+                to,
+                Label(class_label(class))
+            )?,
+            State::Split(a, b) => {
+                writeln!(output, "{} -> {} [label = ε];", id, a)?;
+                writeln!(output, "{} -> {} [label = ε];", id, b)?;
+            }
+            State::Epsilon(to) => writeln!(output, "{} -> {} [label = ε];", id, to)?,
+            State::Save(slot, to) => {
+                let label = Label(format_args!("save {}", slot));
+                writeln!(output, "{} -> {} [label = {}];", id, to, label)?
+            }
+            State::Assert(look, to) => {
+                let look = match look {
+                    Look::StartOfText => "\\A",
+                    Look::EndOfText => "\\z",
+                    Look::StartOfLine => "^",
+                    Look::EndOfLine => "$",
+                    Look::EndOrBeforeFinalNewline => "\\Z",
+                };
+                writeln!(output, "{} -> {} [label = {}];", id, to, Label(look))?
+            }
+            State::Match(_) => {}
         }
-        Ok(())
     }
-
-    fn visit_group(&mut self, node: &ast::Group) -> io::Result<()> {
-        node.inner().accept(self)
+    for (id, state) in nfa.states().iter().enumerate() {
+        let shape = match state {
+            State::Match(_) => "doublecircle",
+            _ => "circle",
+        };
+        writeln!(output, "{} [shape = {}];", id, shape)?;
     }
+    writeln!(output, "}}")
+}
 
-    fn visit_repetition(&mut self, node: &ast::Repetition) -> io::Result<()> {
-        use ast::Quantifier::*;
-        match node.quantifier() {
-            ZeroOrOne => {
-                let start = self.last;
-                node.inner().accept(self)?;
-                writeln!(self.output, "{} -> {} [label = ε];", start, self.last)
-            }
-            ZeroOrMore => {
-                let start = self.last;
-                node.inner().accept(self)?;
-                writeln!(self.output, "{} -> {} [label = ε];", start, self.last)?;
-                writeln!(self.output, "{} -> {} [label = ε];", self.last, start)
-            }
-            OneOrMore => {
-                node.inner().accept(self)?;
-                let start = self.last;
-                node.inner().accept(self)?;
-                writeln!(self.output, "{} -> {} [label = ε];", start, self.last)?;
-                writeln!(self.output, "{} -> {} [label = ε];", self.last, start)
+/// Draws a determinized automaton with one edge per pair of states, labeled
+/// with all the characters leading from one to the other. Dead states and
+/// the edges into them are left out if `omit_dead` is set.
+pub fn dfa_to_dot<W: Write>(dfa: &Dfa, omit_dead: bool, mut output: W) -> io::Result<()> {
+    writeln!(output, "digraph {{\nrankdir = {};", RankDir::default())?;
+    let alphabet = dfa.alphabet();
+    let hidden = |id: usize| omit_dead && dfa.states()[id].is_dead();
+    for (id, state) in dfa.states().iter().enumerate() {
+        if hidden(id) {
+            continue;
+        }
+        let mut targets: Vec<(usize, CharClass)> = Vec::new();
+        for (class, &to) in state.next()[..alphabet.len()].iter().enumerate() {
+            let (a, b) = alphabet.range(class);
+            match targets.iter_mut().find(|(target, _)| *target == to) {
+                Some((_, chars)) => chars.push(a, b),
+                None => targets.push((to, CharClass::from_range(a, b))),
             }
-            Exact(n) => {
-                for _ in 0..n {
-                    node.inner().accept(self)?;
-                }
-                Ok(())
+        }
+        for (to, chars) in targets {
+            if !hidden(to) {
+                writeln!(
+                    output,
+                    "{} -> {} [label = {}];",
+                    id,
+                    to,
+                    Label(class_label(&chars))
+                )?;
             }
-            Minimum(n) => {
-                for _ in 0..n {
-                    node.inner().accept(self)?;
-                }
-                let start = self.last;
-                node.inner().accept(self)?;
-                writeln!(self.output, "{} -> {} [label = ε];", start, self.last)?;
-                writeln!(self.output, "{} -> {} [label = ε];", self.last, start)
+        }
+        // A newline ending the haystack only needs its own edge if it goes
+        // somewhere other than any other newline.
+        let last = state.next()[alphabet.len()];
+        if last != state.next()[alphabet.class_of('\n')] && !hidden(last) {
+            writeln!(output, "{} -> {} [label = \"final \\\\n\"];", id, last)?;
+        }
+    }
+    for (id, state) in dfa.states().iter().enumerate() {
+        if hidden(id) {
+            continue;
+        }
+        // States that only accept once end assertions hold are dashed.
+        let shape = if state.is_accepting() {
+            "doublecircle"
+        } else if state.is_accepting_at_end() {
+            "doublecircle, style = dashed"
+        } else {
+            "circle"
+        };
+        writeln!(output, "{} [shape = {}];", id, shape)?;
+    }
+    writeln!(output, "}}")
+}
+
+/// Lists the ranges of a class, or of its complement if that's shorter.
+fn class_label(class: &CharClass) -> String {
+    let mut complement = class.clone();
+    complement.negate();
+    if complement.is_empty() {
+        return "ANY".to_owned();
+    }
+    let (prefix, ranges) = if class.contains(char::MAX) {
+        ("not ", complement.ranges())
+    } else {
+        ("", class.ranges())
+    };
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|&(a, b)| {
+            if a == b {
+                a.to_string()
+            } else {
+                format!("{}-{}", a, b)
             }
-            Range(n, m) => {
-                let start = self.last;
-                node.inner().accept(self)?;
-                let len = self.last - start;
-                for _ in 1..n {
-                    node.inner().accept(self)?;
-                }
-                let end = self.last + ((m - n) as usize) * len;
-                writeln!(self.output, "{} -> {} [label = ε];", self.last, end)?;
-                for _ in 0..m - n {
-                    node.inner().accept(self)?;
-                    if end - self.last > 1 {
-                        writeln!(self.output, "{} -> {} [label = ε];", self.last, end)?;
-                    }
-                }
-                Ok(())
+        })
+        .collect();
+    format!("{}{}", prefix, ranges.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+    use crate::dfa::determinize;
+    use crate::nfa::{compile, compile_with, Config};
+
+    fn render(pattern: &str) -> String {
+        let mut output = Vec::new();
+        GraphvizCompiler::new(&mut output)
+            .render(&parse(pattern).unwrap())
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Parses `from -> to` edges and the declared nodes out of the output.
+    fn graph(output: &str) -> (Vec<(usize, usize)>, Vec<usize>, usize) {
+        let mut edges = Vec::new();
+        let mut nodes = Vec::new();
+        let mut accepting = None;
+        for line in output.lines() {
+            let (head, _) = match line.find(" [") {
+                Some(at) => line.split_at(at),
+                None => continue,
+            };
+            if let Some(arrow) = head.find(" -> ") {
+                edges.push((
+                    head[..arrow].parse().unwrap(),
+                    head[arrow + 4..].parse().unwrap(),
+                ));
+            } else if line.contains("doublecircle") {
+                accepting = Some(head.parse().unwrap());
+            } else {
+                nodes.push(head.parse().unwrap());
             }
         }
+        (edges, nodes, accepting.unwrap())
+    }
+
+    /// Asserts that nodes are numbered `0..=last`, `last` is the only accepting
+    /// node, every edge connects declared nodes and every node is connected.
+    fn assert_consistent(pattern: &str) {
+        let output = render(pattern);
+        let (edges, nodes, last) = graph(&output);
+        assert_eq!(nodes, (0..last).collect::<Vec<_>>(), "{}", output);
+        for &(from, to) in &edges {
+            assert!(from <= last && to <= last, "{}", output);
+        }
+        for node in 0..=last {
+            assert!(
+                edges.iter().any(|&(from, to)| from == node || to == node),
+                "node {} is disconnected in {}",
+                node,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn render_rankdir() {
+        assert!(render("a").starts_with("digraph {\nrankdir = LR;\n"));
+        let mut output = Vec::new();
+        GraphvizCompiler::new(&mut output)
+            .with_rankdir(RankDir::TopToBottom)
+            .render(&parse("a").unwrap())
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("digraph {\nrankdir = TB;\n"));
+    }
+
+    #[test]
+    fn render_highlight_accepting() {
+        assert!(render("ab").contains("2 [shape = doublecircle];"));
+        let mut output = Vec::new();
+        GraphvizCompiler::new(&mut output)
+            .highlight_accepting(true)
+            .render(&parse("ab").unwrap())
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("2 [shape = doublecircle, style = filled, fillcolor = lightgreen];")
+        );
+        assert!(output.contains("1 [shape = circle];"));
+    }
+
+    #[test]
+    fn render_twice() {
+        let mut output = Vec::new();
+        let mut compiler = GraphvizCompiler::new(&mut output);
+        compiler.render(&parse("abc").unwrap()).unwrap();
+        compiler.render(&parse("a|b").unwrap()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let at = output.rfind("digraph").unwrap();
+        assert_eq!(&output[..at], render("abc"));
+        assert_eq!(&output[at..], render("a|b"));
+    }
+
+    #[test]
+    fn render_to_string() {
+        let mut output = String::new();
+        GraphvizCompiler::from_fmt(&mut output)
+            .render(&parse("aé").unwrap())
+            .unwrap();
+        assert!(output.starts_with("digraph {\nrankdir = LR;\n"));
+        assert_eq!(output, render("aé"));
+    }
+
+    fn render_nfa(pattern: &str) -> String {
+        let mut output = Vec::new();
+        nfa_to_dot(&compile(&parse(pattern).unwrap()).unwrap(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_nfa_alternative() {
+        let output = render_nfa("a|b");
+        let (edges, nodes, accepting) = graph(&output);
+        assert_eq!(edges.len(), 6, "{}", output);
+        assert_eq!(nodes, vec![0, 1, 2, 3, 4]);
+        assert_eq!(accepting, 5);
+        assert!(output.contains(r#"[label = "a"];"#));
+        assert!(output.contains(r#"[label = "b"];"#));
+        assert!(output.contains(r#"[label = "save 1"];"#));
+    }
+
+    #[test]
+    fn render_nfa_class_labels() {
+        assert!(render_nfa("[a-c_]").contains(r#"[label = "_ a-c"];"#));
+        assert!(render_nfa(".").contains(r#"[label = "not \\n"];"#));
+        assert!(render_nfa("(?s).").contains(r#"[label = "ANY"];"#));
+        assert!(render_nfa("[^ab]").contains(r#"[label = "not a-b"];"#));
+        assert!(render_nfa("^").contains(r#"[label = "\\A"];"#));
+    }
+
+    fn render_dfa(pattern: &str, omit_dead: bool) -> String {
+        let dfa = determinize(&compile(&parse(pattern).unwrap()).unwrap());
+        let mut output = Vec::new();
+        dfa_to_dot(&dfa, omit_dead, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_dfa_repetition() {
+        let output = render_dfa("(ab)+", false);
+        assert!(!output.contains("ε"), "{}", output);
+        assert_eq!(output.matches("doublecircle").count(), 1, "{}", output);
+        assert!(output.contains(r#"[label = "a"];"#), "{}", output);
+        let (edges, _, _) = graph(&output);
+        for (i, &(from, to)) in edges.iter().enumerate() {
+            assert!(!edges[..i].contains(&(from, to)), "{}", output);
+        }
+    }
+
+    #[test]
+    fn render_dfa_omit_dead() {
+        let config = Config {
+            anchored: true,
+            ..Config::default()
+        };
+        let dfa = determinize(&compile_with(&parse("ab").unwrap(), config).unwrap());
+        let dead = dfa.states().iter().position(|s| s.is_dead()).unwrap();
+        let render = |omit_dead| {
+            let mut output = Vec::new();
+            dfa_to_dot(&dfa, omit_dead, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let all = render(false);
+        let live = render(true);
+        assert!(all.contains(&format!("{} [shape = circle];", dead)));
+        assert!(!live.contains(&format!("{} [shape = circle];", dead)));
+        assert!(!live.contains(&format!("-> {} ", dead)));
+        assert!(render_dfa("(ab)+", true).contains("doublecircle"));
+        assert!(render_dfa("(ab)+$", true).contains("[shape = doublecircle, style = dashed];"));
+    }
+
+    #[test]
+    fn render_zero_or_one() {
+        assert_consistent("a?");
+        assert_consistent("(ab)?c");
+        assert!(render("a?").contains("0 -> 1 [label = ε];"));
+    }
+
+    #[test]
+    fn render_zero_or_more() {
+        assert_consistent("a*");
+        assert_consistent("x(a|b)*");
+        let output = render("a*");
+        assert!(output.contains("0 -> 1 [label = ε];"));
+        assert!(output.contains("1 -> 0 [label = ε];"));
+    }
+
+    #[test]
+    fn render_one_or_more() {
+        assert_consistent("a+");
+        assert_consistent("[ab]+c");
+        let (edges, _, last) = graph(&render("a+"));
+        assert_eq!(last, 2);
+        assert!(edges.contains(&(2, 1)));
+    }
+
+    #[test]
+    fn render_exact() {
+        assert_consistent("a{3}");
+        assert_consistent("(ab){2}");
+        let (edges, _, last) = graph(&render("a{3}"));
+        assert_eq!(edges, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(last, 3);
+    }
+
+    #[test]
+    fn render_minimum() {
+        assert_consistent("a{2,}");
+        assert_consistent("(a|b){1,}");
+        let (_, _, last) = graph(&render("a{2,}"));
+        assert_eq!(last, 3);
+    }
+
+    #[test]
+    fn render_range() {
+        assert_consistent("a{2,4}");
+        assert_consistent("(ab){1,2}");
+        assert_consistent("[ab]{1,3}");
+        let (edges, _, last) = graph(&render("a{2,4}"));
+        assert_eq!(last, 4);
+        assert_eq!(edges, vec![(0, 1), (1, 2), (2, 3), (3, 4), (2, 4), (3, 4)]);
+        let output = render("a{2,4}");
+        assert!(output.contains("2 -> 4 [label = ε];"));
+        assert!(output.contains("3 -> 4 [label = ε];"));
+        let (edges, _, last) = graph(&render("a{0,2}"));
+        assert_eq!(last, 2);
+        assert_eq!(edges, vec![(0, 1), (1, 2), (0, 2), (1, 2)]);
+        let (edges, _, _) = graph(&render("a{3,3}"));
+        assert_eq!(edges, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_consistent("a{0,1}");
+        assert_consistent("(a|b){0,3}");
+    }
+
+    #[test]
+    fn render_look_around() {
+        assert_consistent("a(?=b)c");
+        let output = render("a(?!b|c)");
+        assert!(
+            output.contains(r#"1 -> 2 [label = "(?!b|c)"];"#),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn render_atomic_like_group() {
+        assert_eq!(render("(?>a|bc)d"), render("(?:a|bc)d"));
+    }
+
+    #[test]
+    fn render_escaped_labels() {
+        let output = render(r#"a"b\\ [ "]"#);
+        assert!(output.contains(r#"1 -> 2 [label = "\""];"#), "{}", output);
+        assert!(output.contains(r#"2 -> 3 [label = "b"];"#), "{}", output);
+        assert!(output.contains(r#"3 -> 4 [label = "\\"];"#), "{}", output);
+        assert!(output.contains(r#"4 -> 5 [label = " "];"#), "{}", output);
+        assert!(render(r"\A").contains(r#"[label = "\\A"];"#));
+    }
+
+    #[test]
+    fn render_control_characters() {
+        let output = render(r"a\nb");
+        assert!(output.contains(r#"1 -> 2 [label = "\\n"];"#), "{}", output);
+        assert!(!output.contains("\"\n\""));
+        assert!(render(r"\t").contains(r#"[label = "\\t"];"#));
+        assert!(render(r"\e").contains(r#"[label = "\\x1b"];"#));
+        assert!(render(r"[^\n]").contains(r#"[label = "not \\n"];"#));
+    }
+
+    #[test]
+    fn render_compact_bracket() {
+        let mut output = Vec::new();
+        GraphvizCompiler::new(&mut output)
+            .compact_brackets(true)
+            .render(&parse("[a-z0-9_]x[^[:digit:]]").unwrap())
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let (edges, _, last) = graph(&output);
+        assert_eq!(edges, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(last, 3);
+        assert!(
+            output.contains(r#"0 -> 1 [label = "a-z 0-9 _"];"#),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(r#"2 -> 3 [label = "not digit"];"#),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn render_bracket() {
+        assert_consistent("[a-z0-9[:space:]]");
+        assert!(render("[a-z]").contains("1 -> 2 [label = \"a-z\"];"));
     }
 }
@@ -0,0 +1,116 @@
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic;
+use std::ptr;
+
+use crate::ast::{self, Ast};
+use crate::dot::GraphvizCompiler;
+
+/// Parses the NUL-terminated `pattern`, returning null if it's null, not
+/// UTF-8 or not a valid pattern. The AST must be freed with `regex_free_ast`.
+///
+/// # Safety
+///
+/// `pattern` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn regex_parse(pattern: *const c_char) -> *mut Ast {
+    if pattern.is_null() {
+        return ptr::null_mut();
+    }
+    let pattern = match CStr::from_ptr(pattern).to_str() {
+        Ok(pattern) => pattern,
+        Err(_) => return ptr::null_mut(),
+    };
+    match ast::parse(pattern) {
+        Ok(ast) => Box::into_raw(Box::new(ast)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Writes the DOT graph for `ast` to `out`, truncated to `len - 1` bytes and
+/// NUL-terminated like `snprintf` does. Returns the length of the whole graph,
+/// so a larger buffer can be retried, or -1 if `ast` is null or rendering
+/// fails. `out` may be null when `len` is 0.
+///
+/// # Safety
+///
+/// `ast` must be null or come from `regex_parse`, and `out` must be valid for
+/// writing `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn regex_render_dot(ast: *const Ast, out: *mut c_char, len: usize) -> c_int {
+    let ast = match ast.as_ref() {
+        Some(ast) => ast,
+        None => return -1,
+    };
+    let rendered = panic::catch_unwind(|| {
+        let mut dot = Vec::new();
+        GraphvizCompiler::new(&mut dot).render(ast).map(|_| dot)
+    });
+    let dot = match rendered {
+        Ok(Ok(dot)) => dot,
+        _ => return -1,
+    };
+    if len > 0 && !out.is_null() {
+        let n = dot.len().min(len - 1);
+        ptr::copy_nonoverlapping(dot.as_ptr(), out.cast(), n);
+        *out.add(n) = 0;
+    }
+    c_int::try_from(dot.len()).unwrap_or(-1)
+}
+
+/// Frees an AST returned by `regex_parse`. Does nothing if `ast` is null.
+///
+/// # Safety
+///
+/// `ast` must be null or come from `regex_parse`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn regex_free_ast(ast: *mut Ast) {
+    if !ast.is_null() {
+        drop(Box::from_raw(ast));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn round_trip() {
+        let pattern = CString::new("a(b|c)*").unwrap();
+        unsafe {
+            let ast = regex_parse(pattern.as_ptr());
+            assert!(!ast.is_null());
+            let len = regex_render_dot(ast, ptr::null_mut(), 0);
+            assert!(len > 0);
+            let mut short = [0x7f as c_char; 8];
+            assert_eq!(regex_render_dot(ast, short.as_mut_ptr(), short.len()), len);
+            assert_eq!(CStr::from_ptr(short.as_ptr()).to_bytes().len(), 7);
+            let mut buf = vec![0 as c_char; len as usize + 1];
+            assert_eq!(regex_render_dot(ast, buf.as_mut_ptr(), buf.len()), len);
+            let dot = CStr::from_ptr(buf.as_ptr()).to_str().unwrap();
+            let mut expected = Vec::new();
+            GraphvizCompiler::new(&mut expected)
+                .render(&ast::parse("a(b|c)*").unwrap())
+                .unwrap();
+            assert_eq!(dot.as_bytes(), &expected[..]);
+            regex_free_ast(ast);
+        }
+    }
+
+    #[test]
+    fn bad_arguments() {
+        unsafe {
+            assert!(regex_parse(ptr::null()).is_null());
+            let invalid = CString::new(vec![b'a', 0xff]).unwrap();
+            assert!(regex_parse(invalid.as_ptr()).is_null());
+            let unbalanced = CString::new("(a").unwrap();
+            assert!(regex_parse(unbalanced.as_ptr()).is_null());
+            let huge = CString::new("a{300}").unwrap();
+            assert!(regex_parse(huge.as_ptr()).is_null());
+            assert_eq!(regex_render_dot(ptr::null(), ptr::null_mut(), 0), -1);
+            regex_free_ast(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::{self, Display, Write as _};
+use std::io::{self, Write};
+
+use crate::ast;
+use crate::layout::{Edges, Layout};
+
+/// Draws the automaton for an AST as a GraphML document, numbered like
+/// `GraphvizCompiler` does, for tools such as yEd or Gephi.
+pub struct GraphMlCompiler<W> {
+    output: W,
+}
+
+impl<W: Write> GraphMlCompiler<W> {
+    pub fn new(output: W) -> GraphMlCompiler<W> {
+        GraphMlCompiler { output }
+    }
+
+    /// Writes a directed graph for `ast`, with nodes marked accepting or not
+    /// and edges carrying their labels, which are empty for epsilon moves.
+    pub fn render(&mut self, ast: &ast::Ast) -> io::Result<()> {
+        let mut edges = GraphMlEdges(Vec::new());
+        let last = Layout::run(ast, &mut edges, false)?;
+        writeln!(self.output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            self.output,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            self.output,
+            r#"  <key id="accepting" for="node" attr.name="accepting" attr.type="boolean"/>"#
+        )?;
+        writeln!(
+            self.output,
+            r#"  <key id="label" for="edge" attr.name="label" attr.type="string"/>"#
+        )?;
+        writeln!(self.output, r#"  <graph id="G" edgedefault="directed">"#)?;
+        for node in 0..=last {
+            writeln!(
+                self.output,
+                r#"    <node id="n{}"><data key="accepting">{}</data></node>"#,
+                node,
+                node == last
+            )?;
+        }
+        self.output.write_all(&edges.0)?;
+        writeln!(self.output, "  </graph>")?;
+        writeln!(self.output, "</graphml>")
+    }
+}
+
+/// Buffers the edges, since GraphML readers expect nodes to come first.
+struct GraphMlEdges(Vec<u8>);
+
+impl Edges for GraphMlEdges {
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) -> io::Result<()> {
+        writeln!(
+            self.0,
+            r#"    <edge source="n{}" target="n{}"><data key="label">{}</data></edge>"#,
+            from,
+            to,
+            Text(label.unwrap_or(""))
+        )
+    }
+}
+
+/// Text escaped for XML. Control characters, which XML 1.0 doesn't allow
+/// even as references, are shown in escape notation, like `\n`.
+struct Text<'a>(&'a str);
+
+impl<'a> Display for Text<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\x{:02x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    fn render(pattern: &str) -> String {
+        let mut output = Vec::new();
+        GraphMlCompiler::new(&mut output)
+            .render(&parse(pattern).unwrap())
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_concatenation() {
+        let output = render("ab");
+        let doc = roxmltree::Document::parse(&output).unwrap();
+        let count = |name| {
+            let nodes = doc.descendants().filter(|n| n.has_tag_name(name));
+            nodes.count()
+        };
+        assert_eq!(count("node"), 3);
+        assert_eq!(count("edge"), 2);
+        assert!(output.contains(r#"<node id="n2"><data key="accepting">true</data></node>"#));
+        assert!(
+            output.contains(r#"<edge source="n0" target="n1"><data key="label">a</data></edge>"#)
+        );
+    }
+
+    #[test]
+    fn render_escapes_labels() {
+        let output = render("<&>\"\n|a?");
+        roxmltree::Document::parse(&output).unwrap();
+        assert!(output.contains(">&lt;<"), "{}", output);
+        assert!(output.contains(">&amp;<"), "{}", output);
+        assert!(output.contains(">\\n<"), "{}", output);
+        assert!(
+            output.contains(r#"<data key="label"></data>"#),
+            "{}",
+            output
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::ast::*;
+use crate::visit::{Visitable, Visitor};
+
+/// Writes `ast` in the syntax of the `regex` crate, matching the same strings,
+/// or returns `None` if it uses `\Z`, look-around or Unicode mode, which have no
+/// counterpart there, or a descending range like `{3,2}`, which it rejects.
+pub fn to_regex_syntax(ast: &Ast) -> Option<String> {
+    ast.accept(&mut RegexSyntax)
+}
+
+/// Characters with a meaning in the `regex` crate outside of classes.
+const META: &str = "\\.+*?()|[]{}^$#&-~";
+
+/// Characters with a meaning in the `regex` crate inside of classes.
+const CLASS_META: &str = "\\[]^-&~";
+
+fn push_char(out: &mut String, c: char, meta: &str) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if c.is_control() => write!(out, "\\x{{{:x}}}", c as u32).unwrap(),
+        c if meta.contains(c) => {
+            out.push('\\');
+            out.push(c);
+        }
+        c => out.push(c),
+    }
+}
+
+/// The flags without `u`, as ASCII mode is what's translated, or `None` if they
+/// enable Unicode mode.
+fn ascii_flags(flags: &Flags) -> Option<Flags> {
+    if flags.unicode() == Some(true) {
+        return None;
+    }
+    Some(Flags::new(
+        flags.case_insensitive(),
+        flags.multi_line(),
+        flags.dot_matches_new_line(),
+        None,
+    ))
+}
+
+struct RegexSyntax;
+
+impl Visitor<Option<String>> for RegexSyntax {
+    fn visit(&mut self, node: &Ast) -> Option<String> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &Literal) -> Option<String> {
+        let mut out = String::new();
+        push_char(&mut out, node.value(), META);
+        Some(out)
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> Option<String> {
+        let mut out = String::new();
+        for c in node.value().chars() {
+            push_char(&mut out, c, META);
+        }
+        Some(out)
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> Option<String> {
+        Some(".".to_string())
+    }
+
+    fn visit_anchor(&mut self, node: &Anchor) -> Option<String> {
+        match node {
+            Anchor::EndOrBeforeFinalNewline => None,
+            anchor => Some(anchor.to_string()),
+        }
+    }
+
+    fn visit_perl_class(&mut self, node: &PerlClass) -> Option<String> {
+        // The `regex` crate's classes are Unicode-aware, and ours are ASCII.
+        let ranges = match node.kind() {
+            PerlClassKind::Digit => "0-9",
+            PerlClassKind::Space => "\\t-\\r ",
+            PerlClassKind::Word => "0-9A-Z_a-z",
+        };
+        let negation = if node.negated() { "^" } else { "" };
+        Some(format!("[{}{}]", negation, ranges))
+    }
+
+    fn visit_flags(&mut self, node: &Flags) -> Option<String> {
+        match ascii_flags(node)? {
+            flags if flags == Flags::default() => Some(String::new()),
+            flags => Some(format!("(?{})", flags)),
+        }
+    }
+
+    fn visit_bracket(&mut self, node: &Bracket) -> Option<String> {
+        let mut out = String::from(if node.negated() { "[^" } else { "[" });
+        for expr in node.exprs() {
+            match *expr {
+                BracketExpr::Char(c) => push_char(&mut out, c, CLASS_META),
+                BracketExpr::Range(a, b) => {
+                    push_char(&mut out, a, CLASS_META);
+                    out.push('-');
+                    push_char(&mut out, b, CLASS_META);
+                }
+                BracketExpr::Class(ref class, false) => write!(out, "[:{}:]", class).unwrap(),
+                BracketExpr::Class(ref class, true) => write!(out, "[:^{}:]", class).unwrap(),
+                BracketExpr::UnicodeProperty { .. } => write!(out, "{}", expr).unwrap(),
+            }
+        }
+        out.push(']');
+        Some(out)
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> Option<String> {
+        let mut out = String::new();
+        for item in node.items() {
+            match item {
+                Ast::Alternative(_) => write!(out, "(?:{})", self.visit(item)?).unwrap(),
+                _ => out += &self.visit(item)?,
+            }
+        }
+        Some(out)
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> Option<String> {
+        let items: Option<Vec<_>> = node.items().iter().map(|x| self.visit(x)).collect();
+        Some(items?.join("|"))
+    }
+
+    fn visit_group(&mut self, node: &Group) -> Option<String> {
+        let inner = self.visit(node.inner())?;
+        Some(match node.kind() {
+            GroupKind::Capturing => format!("({})", inner),
+            GroupKind::Named(name) => format!("(?P<{}>{})", name, inner),
+            GroupKind::NonCapturing(flags) => format!("(?{}:{})", ascii_flags(flags)?, inner),
+        })
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> Option<String> {
+        if let Quantifier::Range(n, m) = node.quantifier() {
+            if n > m {
+                return None;
+            }
+        }
+        let inner = self.visit(node.inner())?;
+        let atomic = match node.inner() {
+            Ast::Literals(literals) => literals.value().chars().count() == 1,
+            Ast::Concatenation(_) | Ast::Alternative(_) | Ast::Repetition(_) => false,
+            _ => true,
+        };
+        if atomic {
+            Some(format!("{}{}", inner, node.quantifier()))
+        } else {
+            Some(format!("(?:{}){}", inner, node.quantifier()))
+        }
+    }
+
+    fn visit_look(&mut self, _node: &LookAround) -> Option<String> {
+        None
+    }
+
+    fn visit_atomic(&mut self, _node: &Ast) -> Option<String> {
+        None
+    }
+
+    fn visit_backreference(&mut self, _node: &Backreference) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(feature = "regex-syntax")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConversionError {
+    /// The HIR uses something the AST can't express.
+    Unsupported(String),
+}
+
+#[cfg(feature = "regex-syntax")]
+impl core::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ConversionError::Unsupported(what) => write!(f, "unsupported {}", what),
+        }
+    }
+}
+
+#[cfg(feature = "regex-syntax")]
+impl core::error::Error for ConversionError {}
+
+/// Converts `hir` from the `regex-syntax` crate into an equivalent AST.
+#[cfg(feature = "regex-syntax")]
+pub fn from_hir(hir: &regex_syntax::hir::Hir) -> Result<Ast, ConversionError> {
+    use core::convert::TryFrom;
+    use regex_syntax::hir::{self, HirKind};
+    let unsupported = |what: &str| Err(ConversionError::Unsupported(what.to_string()));
+    Ok(match hir.kind() {
+        HirKind::Empty => Ast::Concatenation(Concatenation::new(Vec::new())),
+        HirKind::Literal(hir::Literal(bytes)) => {
+            let value = match core::str::from_utf8(bytes) {
+                Ok(value) => value,
+                Err(_) => return unsupported("literal bytes"),
+            };
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ast::Literal(Literal::new(c)),
+                _ => Ast::Literals(Literals::new(value.to_string())),
+            }
+        }
+        HirKind::Class(hir::Class::Unicode(class)) => {
+            let ranges = class.ranges().iter().map(|r| (r.start(), r.end()));
+            Ast::Bracket(Bracket::new(ranges.map(bracket_expr).collect(), false))
+        }
+        HirKind::Class(hir::Class::Bytes(class)) => {
+            if class.ranges().iter().any(|r| r.end() > 0x7f) {
+                return unsupported("class of non-ASCII bytes");
+            }
+            let ranges = class.ranges().iter();
+            let ranges = ranges.map(|r| (char::from(r.start()), char::from(r.end())));
+            Ast::Bracket(Bracket::new(ranges.map(bracket_expr).collect(), false))
+        }
+        HirKind::Look(look) => {
+            let multi_line = Flags::new(None, Some(true), None, None);
+            let multi_line = |anchor| {
+                let kind = GroupKind::NonCapturing(multi_line);
+                Ast::Group(Group::with_kind(Ast::Anchor(anchor), kind))
+            };
+            match look {
+                hir::Look::Start => Ast::Anchor(Anchor::StartOfText),
+                hir::Look::End => Ast::Anchor(Anchor::EndOfText),
+                hir::Look::StartLF => multi_line(Anchor::Start),
+                hir::Look::EndLF => multi_line(Anchor::End),
+                _ => return unsupported("look-around assertion"),
+            }
+        }
+        HirKind::Repetition(rep) => {
+            if !rep.greedy {
+                return unsupported("lazy repetition");
+            }
+            let bound = |n: u32| {
+                u8::try_from(n).map_err(|_| ConversionError::Unsupported("repetition bound".into()))
+            };
+            let quantifier = match (rep.min, rep.max) {
+                (0, Some(1)) => Quantifier::ZeroOrOne,
+                (0, None) => Quantifier::ZeroOrMore,
+                (1, None) => Quantifier::OneOrMore,
+                (n, None) => Quantifier::Minimum(bound(n)?),
+                (n, Some(m)) if n == m => Quantifier::Exact(bound(n)?),
+                (n, Some(m)) => Quantifier::Range(bound(n)?, bound(m)?),
+            };
+            Ast::Repetition(Repetition::new(from_hir(&rep.sub)?, quantifier))
+        }
+        HirKind::Capture(capture) => {
+            let kind = match &capture.name {
+                Some(name) => GroupKind::Named(name.to_string()),
+                None => GroupKind::Capturing,
+            };
+            Ast::Group(Group::with_kind(from_hir(&capture.sub)?, kind))
+        }
+        HirKind::Concat(items) => {
+            let items: Result<_, _> = items.iter().map(from_hir).collect();
+            Ast::Concatenation(Concatenation::new(items?))
+        }
+        HirKind::Alternation(items) => {
+            let items: Result<_, _> = items.iter().map(from_hir).collect();
+            Ast::Alternative(Alternative::new(items?))
+        }
+    })
+}
+
+#[cfg(feature = "regex-syntax")]
+fn bracket_expr((a, b): (char, char)) -> BracketExpr {
+    if a == b {
+        BracketExpr::Char(a)
+    } else {
+        BracketExpr::Range(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(pattern: &str) -> String {
+        to_regex_syntax(&parse(pattern).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn regex_syntax() {
+        assert_eq!(convert("a.b|c"), "a.b|c");
+        assert_eq!(convert(r"\d+\W"), "[0-9]+[^0-9A-Z_a-z]");
+        assert_eq!(convert("[a-c[:digit:]-]"), r"[a-c[:digit:]\-]");
+        assert_eq!(convert("[]&~[]"), r"[\]\&\~\[]");
+        assert_eq!(convert(r"a#b&\e"), r"a\#b\&\x{1b}");
+        assert_eq!(convert("(?i:x)(?P<n>y){2,3}"), "(?i:x)(?P<n>y){2,3}");
+        assert_eq!(convert(r"(?i-u:x)(?-u)\d"), "(?i:x)[0-9]");
+        assert_eq!(to_regex_syntax(&parse(r"a\Z").unwrap()), None);
+        assert_eq!(to_regex_syntax(&parse(r"(?u)\d").unwrap()), None);
+        assert_eq!(to_regex_syntax(&parse("a{3,2}").unwrap()), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn parses_under_regex_crate() {
+        let cases: &[(&str, &[&str], &[&str])] = &[
+            (r"^\d{2,4}-[[:alpha:]]+$", &["2024-abc"], &["2024-", "1-ab"]),
+            ("(?i)ab|c[]x-]", &["AB", "c]", "c-", "CX"], &["ca"]),
+            (r"x[&&~]y\s", &["x&y ", "x~y\t"], &["x-y "]),
+            ("(?s:.)(?m:^a$)", &["\nb\na"], &["a"]),
+            ("(?P<word>\\w+)\\.", &["snake_case."], &["."]),
+            ("^[[:^digit:]_]+$", &["ab_", "é"], &["a1"]),
+        ];
+        for &(pattern, matching, other) in cases {
+            let ours = crate::Regex::new(pattern).unwrap();
+            let theirs = regex_crate::Regex::new(&convert(pattern)).unwrap();
+            for &haystack in matching {
+                assert!(ours.is_match(haystack), "{} on {:?}", pattern, haystack);
+                assert!(theirs.is_match(haystack), "{} on {:?}", pattern, haystack);
+            }
+            for &haystack in other {
+                assert!(!ours.is_match(haystack), "{} on {:?}", pattern, haystack);
+                assert!(!theirs.is_match(haystack), "{} on {:?}", pattern, haystack);
+            }
+        }
+    }
+
+    #[cfg(feature = "regex-syntax")]
+    fn import(pattern: &str) -> Result<Ast, ConversionError> {
+        from_hir(&regex_syntax::parse(pattern).unwrap())
+    }
+
+    #[test]
+    #[cfg(feature = "regex-syntax")]
+    fn import_hir() {
+        assert_eq!(import("abc"), Ok(parse("abc").unwrap()));
+        assert_eq!(import("(?P<x>a)+"), Ok(parse("(?P<x>a)+").unwrap()));
+        assert_eq!(import("a{2,3}"), Ok(parse("a{2,3}").unwrap()));
+        assert_eq!(import("(?m)^$"), Ok(parse("(?m:^)(?m:$)").unwrap()));
+        assert_eq!(import("[a-cx]"), Ok(parse("[a-cx]").unwrap()));
+        assert!(import(r"\ba").is_err());
+        assert!(import("a+?").is_err());
+        assert!(import("a{300}").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "regex-syntax")]
+    fn import_hir_to_dot() {
+        let ast = import("a(b|cd)*").unwrap();
+        let mut output = Vec::new();
+        crate::dot::GraphvizCompiler::new(&mut output)
+            .render(&ast)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut expected = Vec::new();
+        crate::dot::GraphvizCompiler::new(&mut expected)
+            .render(&parse("a(b|cd)*").unwrap())
+            .unwrap();
+        assert_eq!(output, String::from_utf8(expected).unwrap());
+    }
+}
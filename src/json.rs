@@ -0,0 +1,107 @@
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast;
+use crate::layout::{Edges, Layout};
+
+/// Automaton drawn for an AST, numbered like `GraphvizCompiler` does.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: usize,
+    pub accepting: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// `None` for epsilon transitions.
+    pub label: Option<String>,
+}
+
+impl Graph {
+    pub fn from_ast(ast: &ast::Ast) -> Graph {
+        let mut graph = Graph::default();
+        let last = Layout::run(ast, &mut graph, false).expect("collecting edges can't fail");
+        graph.nodes = (0..=last)
+            .map(|id| Node {
+                id,
+                accepting: id == last,
+            })
+            .collect();
+        graph
+    }
+}
+
+impl Edges for Graph {
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) -> io::Result<()> {
+        self.edges.push(Edge {
+            from,
+            to,
+            label: label.map(str::to_owned),
+        });
+        Ok(())
+    }
+}
+
+/// Writes the automaton for an AST as a JSON `Graph`.
+pub struct JsonGraphCompiler<W> {
+    output: W,
+}
+
+impl<W: Write> JsonGraphCompiler<W> {
+    pub fn new(output: W) -> JsonGraphCompiler<W> {
+        JsonGraphCompiler { output }
+    }
+
+    pub fn render(&mut self, ast: &ast::Ast) -> io::Result<()> {
+        serde_json::to_writer(&mut self.output, &Graph::from_ast(ast))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    fn render(pattern: &str) -> String {
+        let mut output = Vec::new();
+        JsonGraphCompiler::new(&mut output)
+            .render(&parse(pattern).unwrap())
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_alternative() {
+        let graph: Graph = serde_json::from_str(&render("a|b")).unwrap();
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.edges.len(), 6);
+        assert_eq!(graph.nodes.iter().filter(|node| node.accepting).count(), 1);
+        assert!(graph.nodes[5].accepting);
+        assert_eq!(
+            graph
+                .edges
+                .iter()
+                .filter(|edge| edge.label.is_none())
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn render_format() {
+        assert_eq!(
+            render("a"),
+            r#"{"nodes":[{"id":0,"accepting":false},{"id":1,"accepting":true}],"edges":[{"from":0,"to":1,"label":"a"}]}"#
+        );
+    }
+}
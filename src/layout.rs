@@ -0,0 +1,267 @@
+use std::io;
+
+use crate::ast;
+use crate::visit::{Visitable, Visitor};
+
+/// Receives the edges of the automaton laid out for an AST.
+pub(crate) trait Edges {
+    /// Adds an edge, where a missing label stands for an epsilon transition.
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) -> io::Result<()>;
+}
+
+/// Numbers the nodes of an automaton drawn after an AST, from 0 for the start
+/// to `last` for the only accepting node, and passes its edges on.
+pub(crate) struct Layout<'e, E> {
+    last: usize,
+    /// Highest node id referenced by an edge, which must end up declared.
+    highest: usize,
+    /// Whether to draw brackets as a single edge.
+    compact_brackets: bool,
+    edges: &'e mut E,
+}
+
+impl<'e, E: Edges> Layout<'e, E> {
+    /// Lays out `ast`, returning the number of the accepting node.
+    pub fn run(ast: &ast::Ast, edges: &'e mut E, compact_brackets: bool) -> io::Result<usize> {
+        let mut layout = Layout {
+            last: 0,
+            highest: 0,
+            compact_brackets,
+            edges,
+        };
+        ast.accept(&mut layout)?;
+        debug_assert!(
+            layout.highest <= layout.last,
+            "edge to undeclared node {}",
+            layout.highest
+        );
+        Ok(layout.last)
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) -> io::Result<()> {
+        self.highest = self.highest.max(from).max(to);
+        self.edges.edge(from, to, Some(label))
+    }
+
+    fn epsilon(&mut self, from: usize, to: usize) -> io::Result<()> {
+        self.highest = self.highest.max(from).max(to);
+        self.edges.edge(from, to, None)
+    }
+
+    /// Visits `node`, checking that it only appended nodes.
+    fn append(&mut self, node: &ast::Ast) -> io::Result<()> {
+        let last = self.last;
+        node.accept(self)?;
+        debug_assert!(self.last >= last, "node ids went back");
+        Ok(())
+    }
+}
+
+impl<'e, E: Edges> Visitor<io::Result<()>> for Layout<'e, E> {
+    fn visit(&mut self, node: &ast::Ast) -> io::Result<()> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &ast::Literal) -> io::Result<()> {
+        self.last += 1;
+        self.edge(self.last - 1, self.last, &node.value().to_string())
+    }
+
+    fn visit_literals(&mut self, node: &ast::Literals) -> io::Result<()> {
+        for c in node.value().chars() {
+            self.last += 1;
+            self.edge(self.last - 1, self.last, &c.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn visit_wildcard(&mut self, _: &ast::Wildcard) -> io::Result<()> {
+        self.last += 1;
+        self.edge(self.last - 1, self.last, "ANY")
+    }
+
+    fn visit_anchor(&mut self, node: &ast::Anchor) -> io::Result<()> {
+        use ast::Anchor::*;
+        let trans = match node {
+            Start => "^",
+            End => "$",
+            StartOfText => "\\A",
+            EndOfText => "\\z",
+            EndOrBeforeFinalNewline => "\\Z",
+        };
+        self.last += 1;
+        self.edge(self.last - 1, self.last, trans)
+    }
+
+    fn visit_perl_class(&mut self, node: &ast::PerlClass) -> io::Result<()> {
+        use ast::PerlClassKind::*;
+        let trans = match (node.kind(), node.negated()) {
+            (Digit, false) => "\\d",
+            (Digit, true) => "\\D",
+            (Space, false) => "\\s",
+            (Space, true) => "\\S",
+            (Word, false) => "\\w",
+            (Word, true) => "\\W",
+        };
+        self.last += 1;
+        self.edge(self.last - 1, self.last, trans)
+    }
+
+    fn visit_flags(&mut self, _: &ast::Flags) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn visit_bracket(&mut self, node: &ast::Bracket) -> io::Result<()> {
+        let negated = if node.negated() { "not " } else { "" };
+        if self.compact_brackets {
+            let exprs: Vec<String> = node.exprs().iter().map(bracket_expr_label).collect();
+            self.last += 1;
+            return self.edge(
+                self.last - 1,
+                self.last,
+                &format!("{}{}", negated, exprs.join(" ")),
+            );
+        }
+        let start = self.last;
+        for expr in node.exprs() {
+            self.epsilon(start, self.last + 1)?;
+            self.edge(
+                self.last + 1,
+                self.last + 2,
+                &format!("{}{}", negated, bracket_expr_label(expr)),
+            )?;
+            self.last += 2;
+        }
+        self.last += 1;
+        for id in ((start + 2)..self.last).step_by(2) {
+            self.epsilon(id, self.last)?;
+        }
+        Ok(())
+    }
+
+    fn visit_concatenation(&mut self, node: &ast::Concatenation) -> io::Result<()> {
+        for node in node.items() {
+            self.append(node)?
+        }
+        Ok(())
+    }
+
+    fn visit_alternative(&mut self, node: &ast::Alternative) -> io::Result<()> {
+        let start = self.last;
+        let mut accepting = Vec::with_capacity(node.items().len());
+        for node in node.items() {
+            self.last += 1;
+            self.epsilon(start, self.last)?;
+            self.append(node)?;
+            accepting.push(self.last);
+        }
+        self.last += 1;
+        for id in accepting {
+            self.epsilon(id, self.last)?;
+        }
+        Ok(())
+    }
+
+    fn visit_group(&mut self, node: &ast::Group) -> io::Result<()> {
+        self.append(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &ast::Repetition) -> io::Result<()> {
+        use ast::Quantifier::*;
+        match node.quantifier() {
+            ZeroOrOne => {
+                let start = self.last;
+                self.append(node.inner())?;
+                self.epsilon(start, self.last)
+            }
+            ZeroOrMore => {
+                let start = self.last;
+                self.append(node.inner())?;
+                self.epsilon(start, self.last)?;
+                self.epsilon(self.last, start)
+            }
+            OneOrMore => {
+                self.append(node.inner())?;
+                let start = self.last;
+                self.append(node.inner())?;
+                self.epsilon(start, self.last)?;
+                self.epsilon(self.last, start)
+            }
+            Exact(n) => {
+                for _ in 0..n {
+                    self.append(node.inner())?;
+                }
+                Ok(())
+            }
+            Minimum(n) => {
+                for _ in 0..n {
+                    self.append(node.inner())?;
+                }
+                let start = self.last;
+                self.append(node.inner())?;
+                self.epsilon(start, self.last)?;
+                self.epsilon(self.last, start)
+            }
+            Range(n, m) => {
+                for _ in 0..n {
+                    self.append(node.inner())?;
+                }
+                // Each optional copy can be skipped, jumping straight to the end.
+                let mut skips = Vec::new();
+                for _ in n..m {
+                    skips.push(self.last);
+                    self.append(node.inner())?;
+                }
+                for from in skips {
+                    self.epsilon(from, self.last)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Draws a look-around as a single zero-width edge labeled with it.
+    fn visit_look(&mut self, node: &ast::LookAround) -> io::Result<()> {
+        self.last += 1;
+        self.edge(self.last - 1, self.last, &node.to_string())
+    }
+
+    fn visit_atomic(&mut self, node: &ast::Ast) -> io::Result<()> {
+        self.append(node)
+    }
+
+    fn visit_backreference(&mut self, node: &ast::Backreference) -> io::Result<()> {
+        self.last += 1;
+        self.edge(self.last - 1, self.last, &node.to_string())
+    }
+}
+
+fn bracket_expr_label(expr: &ast::BracketExpr) -> String {
+    match expr {
+        ast::BracketExpr::Char(c) => c.to_string(),
+        ast::BracketExpr::Range(a, b) => format!("{}-{}", a, b),
+        ast::BracketExpr::Class(class, negated) => {
+            use ast::Class::*;
+            let name = match class {
+                Alnum => "alphanumeric",
+                Alpha => "alpha",
+                Blank => "blank",
+                Cntrl => "control",
+                Digit => "digit",
+                Graph => "graph",
+                Lower => "lowercase",
+                Print => "printable",
+                Punct => "punctuation",
+                Space => "whitespace",
+                Upper => "uppercase",
+                Xdigit => "hexadecimal",
+            };
+            if *negated {
+                format!("not {}", name)
+            } else {
+                name.to_owned()
+            }
+        }
+        ast::BracketExpr::UnicodeProperty { .. } => expr.to_string(),
+    }
+}
@@ -1,5 +1,41 @@
+// Clippy assumes `regex::Regex` is the regex crate and validates patterns
+// against its syntax, which differs from ours.
+#![allow(clippy::invalid_regex)]
+// Without `std`, only the parser, the AST and what works on it are available.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 extern crate nom;
 
+pub mod analysis;
 pub mod ast;
+pub mod class;
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod dfa;
+#[cfg(feature = "std")]
 pub mod dot;
-mod visit;
+#[cfg(feature = "std")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod graphml;
+pub mod interop;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "std")]
+mod layout;
+#[cfg(feature = "std")]
+pub mod mermaid;
+pub mod nfa;
+pub mod pretty;
+pub mod program;
+#[cfg(feature = "std")]
+pub mod regex;
+pub mod simplify;
+mod unicode_tables;
+pub mod validate;
+pub mod visit;
+
+#[cfg(feature = "std")]
+pub use crate::regex::{Captures, Error, Match, Regex, RegexBuilder, RegexSet, SetMatches};
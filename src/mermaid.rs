@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Write as _};
+use std::io::{self, Write};
+
+use crate::ast;
+use crate::dot::RankDir;
+use crate::layout::{Edges, Layout};
+
+/// Draws the automaton for an AST as a Mermaid flowchart, numbered like
+/// `GraphvizCompiler` does.
+pub struct MermaidCompiler<W> {
+    rankdir: RankDir,
+    output: W,
+}
+
+impl<W: Write> MermaidCompiler<W> {
+    pub fn new(output: W) -> MermaidCompiler<W> {
+        MermaidCompiler {
+            rankdir: RankDir::default(),
+            output,
+        }
+    }
+
+    pub fn with_rankdir(mut self, rankdir: RankDir) -> MermaidCompiler<W> {
+        self.rankdir = rankdir;
+        self
+    }
+
+    /// Writes a flowchart for `ast`, with the accepting node drawn as a
+    /// double circle.
+    pub fn render(&mut self, ast: &ast::Ast) -> io::Result<()> {
+        writeln!(self.output, "flowchart {}", self.rankdir)?;
+        let last = Layout::run(ast, &mut MermaidEdges(&mut self.output), false)?;
+        for node in 0..last {
+            writeln!(self.output, "    n{0}((\"{0}\"))", node)?;
+        }
+        writeln!(self.output, "    n{0}(((\"{0}\")))", last)
+    }
+}
+
+struct MermaidEdges<'w, W>(&'w mut W);
+
+impl<'w, W: Write> Edges for MermaidEdges<'w, W> {
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) -> io::Result<()> {
+        match label {
+            Some(label) => writeln!(self.0, "    n{} -->|{}| n{}", from, Label(label), to),
+            None => writeln!(self.0, "    n{} -->|ε| n{}", from, to),
+        }
+    }
+}
+
+/// Edge label text, quoted for Mermaid, which takes HTML-like entity codes.
+/// Control characters are shown in escape notation, like `\n`.
+struct Label<'a>(&'a str);
+
+impl<'a> Display for Label<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("#quot;")?,
+                '#' => f.write_str("#35;")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\x{:02x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    fn render(pattern: &str) -> String {
+        let mut output = Vec::new();
+        MermaidCompiler::new(&mut output)
+            .render(&parse(pattern).unwrap())
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn render_concatenation() {
+        assert_eq!(
+            render("ab"),
+            "flowchart LR\n\
+             \x20   n0 -->|\"a\"| n1\n\
+             \x20   n1 -->|\"b\"| n2\n\
+             \x20   n0((\"0\"))\n\
+             \x20   n1((\"1\"))\n\
+             \x20   n2(((\"2\")))\n"
+        );
+    }
+
+    #[test]
+    fn render_epsilon_and_labels() {
+        let output = render("a?");
+        assert!(output.contains("n0 -->|ε| n1"));
+        let output = render("\"#\\n");
+        assert!(output.contains(r##"n0 -->|"#quot;"| n1"##), "{}", output);
+        assert!(output.contains(r##"n1 -->|"#35;"| n2"##), "{}", output);
+        assert!(output.contains(r#"n2 -->|"\n"| n3"#), "{}", output);
+    }
+}
@@ -0,0 +1,1283 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+use core::mem;
+
+use crate::ast;
+use crate::class::CharClass;
+use crate::visit::{Visitable, Visitor};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    /// Consumes a single character from the class.
+    Class(CharClass, usize),
+    /// Follows both transitions, preferring the first one.
+    Split(usize, usize),
+    Epsilon(usize),
+    /// Records the current position in a slot.
+    Save(usize, usize),
+    /// Continues only if the position satisfies the assertion.
+    Assert(Look, usize),
+    /// Accepts, for the pattern with the given index in a set, or 0.
+    Match(usize),
+}
+
+/// Zero-width assertion about the current position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Look {
+    StartOfText,
+    EndOfText,
+    StartOfLine,
+    EndOfLine,
+    EndOrBeforeFinalNewline,
+}
+
+impl Look {
+    /// Checks the assertion at byte offset `pos`. Only `\n` is significant,
+    /// so this works the same for UTF-8 and raw byte haystacks.
+    pub fn is_satisfied(self, haystack: &[u8], pos: usize) -> bool {
+        match self {
+            Look::StartOfText => pos == 0,
+            Look::EndOfText => pos == haystack.len(),
+            Look::StartOfLine => pos == 0 || haystack[pos - 1] == b'\n',
+            Look::EndOfLine => pos == haystack.len() || haystack[pos] == b'\n',
+            Look::EndOrBeforeFinalNewline => {
+                pos == haystack.len() || (pos + 1 == haystack.len() && haystack[pos] == b'\n')
+            }
+        }
+    }
+}
+
+/// Haystack the NFA can run over.
+pub trait Input {
+    fn as_bytes(&self) -> &[u8];
+
+    /// Decodes the character starting at byte offset `pos`, returning it along
+    /// with its length in bytes.
+    fn decode(&self, pos: usize) -> Option<(char, usize)>;
+}
+
+impl Input for str {
+    fn as_bytes(&self) -> &[u8] {
+        str::as_bytes(self)
+    }
+
+    fn decode(&self, pos: usize) -> Option<(char, usize)> {
+        let c = self[pos..].chars().next()?;
+        Some((c, c.len_utf8()))
+    }
+}
+
+/// Each byte is read as the character with the same value, U+0000 through
+/// U+00FF, so patterns can match arbitrary bytes.
+impl Input for [u8] {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn decode(&self, pos: usize) -> Option<(char, usize)> {
+        self.get(pos).map(|&b| (char::from(b), 1))
+    }
+}
+
+/// Thompson NFA constructed from an `Ast`.
+#[derive(Clone, Debug)]
+pub struct Nfa {
+    states: Vec<State>,
+    start: usize,
+    anchored: bool,
+    /// Name of each capturing group, after `None` for the whole match.
+    capture_names: Vec<Option<String>>,
+    /// Number of patterns compiled together.
+    patterns: usize,
+}
+
+impl Nfa {
+    pub fn states(&self) -> &[State] {
+        &self.states
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Whether matches may only begin where the search starts.
+    pub fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+
+    /// Number of capturing groups, plus one for the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.capture_names.len()
+    }
+
+    /// Name of each capturing group, or `None` if it's unnamed, starting with
+    /// `None` for the whole match.
+    pub fn capture_names(&self) -> &[Option<String>] {
+        &self.capture_names
+    }
+
+    /// Number of patterns compiled into the NFA, which is 1 unless it came
+    /// from `compile_set`.
+    pub fn patterns_len(&self) -> usize {
+        self.patterns
+    }
+
+    /// Estimates the heap memory used by the states, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let classes: usize = self
+            .states
+            .iter()
+            .map(|state| match state {
+                State::Class(class, _) => mem::size_of_val(class.ranges()),
+                _ => 0,
+            })
+            .sum();
+        self.states.len() * mem::size_of::<State>() + classes
+    }
+
+    /// Finds the leftmost-first match starting at or after `start`, returning
+    /// its byte range.
+    pub fn search<H: Input + ?Sized>(&self, haystack: &H, start: usize) -> Option<(usize, usize)> {
+        self.search_within(haystack, start, haystack.as_bytes().len())
+    }
+
+    /// Like `search`, but doesn't consume any input past `end`. Assertions
+    /// still see the whole haystack.
+    pub fn search_within<H: Input + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let mut slots = [None; 2];
+        let stats = &mut MatchStats::default();
+        if !self.run(haystack, start, end, false, &mut slots, stats) {
+            return None;
+        }
+        Some((slots[0].unwrap(), slots[1].unwrap()))
+    }
+
+    /// Like `search`, but also reports how much work the search took.
+    pub fn search_with_stats<H: Input + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+    ) -> (Option<(usize, usize)>, MatchStats) {
+        let mut slots = [None; 2];
+        let mut stats = MatchStats::default();
+        let end = haystack.as_bytes().len();
+        if !self.run(haystack, start, end, false, &mut slots, &mut stats) {
+            return (None, stats);
+        }
+        (Some((slots[0].unwrap(), slots[1].unwrap())), stats)
+    }
+
+    /// Returns the end of the first match to complete at or after `start`,
+    /// which isn't necessarily where the leftmost-first match ends.
+    pub fn shortest<H: Input + ?Sized>(&self, haystack: &H, start: usize) -> Option<usize> {
+        let mut slots = [None; 2];
+        let end = haystack.as_bytes().len();
+        if !self.run(
+            haystack,
+            start,
+            end,
+            true,
+            &mut slots,
+            &mut MatchStats::default(),
+        ) {
+            return None;
+        }
+        slots[1]
+    }
+
+    /// Like `search`, but returns the start and end of every capturing group
+    /// in turn, which are `None` for groups that didn't participate.
+    pub fn captures<H: Input + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+    ) -> Option<Vec<Option<usize>>> {
+        let mut slots = vec![None; 2 * self.captures_len()];
+        let end = haystack.as_bytes().len();
+        if !self.run(
+            haystack,
+            start,
+            end,
+            false,
+            &mut slots,
+            &mut MatchStats::default(),
+        ) {
+            return None;
+        }
+        Some(slots)
+    }
+
+    /// Returns every position a match starting exactly at `start` can end at,
+    /// in increasing order.
+    pub fn match_ends<H: Input + ?Sized>(&self, haystack: &H, start: usize) -> Vec<usize> {
+        let bytes = haystack.as_bytes();
+        let mut clist = Threads::new(self.states.len(), 0);
+        let mut nlist = Threads::new(self.states.len(), 0);
+        let mut stack = Vec::new();
+        let mut ends = Vec::new();
+        let mut pos = start;
+        self.add_thread(&mut clist, self.start, bytes, pos, &mut [], &mut stack);
+        while !clist.is_empty() {
+            let next = haystack.decode(pos);
+            for &id in &clist.dense {
+                match &self.states[id] {
+                    State::Class(class, to) => {
+                        if let Some((_, len)) = next.filter(|&(c, _)| class.contains(c)) {
+                            self.add_thread(&mut nlist, *to, bytes, pos + len, &mut [], &mut stack);
+                        }
+                    }
+                    State::Match(_) => ends.push(pos),
+                    _ => {}
+                }
+            }
+            match next {
+                Some((_, len)) => pos += len,
+                None => break,
+            }
+            core::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        ends
+    }
+
+    /// Returns whether each of the patterns matches somewhere in `haystack`.
+    pub fn which_match<H: Input + ?Sized>(&self, haystack: &H) -> Vec<bool> {
+        let bytes = haystack.as_bytes();
+        let mut matched = vec![false; self.patterns];
+        let mut left = self.patterns;
+        let mut clist = Threads::new(self.states.len(), 0);
+        let mut nlist = Threads::new(self.states.len(), 0);
+        let mut stack = Vec::new();
+        let mut pos = 0;
+        loop {
+            if pos == 0 || !self.anchored {
+                self.add_thread(&mut clist, self.start, bytes, pos, &mut [], &mut stack);
+            }
+            if clist.is_empty() {
+                break;
+            }
+            let next = haystack.decode(pos);
+            for &id in &clist.dense {
+                match &self.states[id] {
+                    State::Class(class, to) => {
+                        if let Some((_, len)) = next.filter(|&(c, _)| class.contains(c)) {
+                            self.add_thread(&mut nlist, *to, bytes, pos + len, &mut [], &mut stack);
+                        }
+                    }
+                    State::Match(pattern) if !matched[*pattern] => {
+                        matched[*pattern] = true;
+                        left -= 1;
+                    }
+                    _ => {}
+                }
+            }
+            if left == 0 {
+                break;
+            }
+            match next {
+                Some((_, len)) => pos += len,
+                None => break,
+            }
+            core::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        matched
+    }
+
+    /// Runs the Pike VM, stopping at the first completed match if `earliest`.
+    /// Positions are only tracked for as many slots as `slots` has, which are
+    /// set to those of the match if there is one.
+    fn run<H: Input + ?Sized>(
+        &self,
+        haystack: &H,
+        start: usize,
+        end: usize,
+        earliest: bool,
+        slots: &mut [Option<usize>],
+        stats: &mut MatchStats,
+    ) -> bool {
+        let bytes = haystack.as_bytes();
+        let mut clist = Threads::new(self.states.len(), slots.len());
+        let mut nlist = Threads::new(self.states.len(), slots.len());
+        let mut scratch = vec![None; slots.len()];
+        let mut stack = Vec::new();
+        let mut matched = false;
+        let mut pos = start;
+        loop {
+            if !matched && (pos == start || !self.anchored) {
+                scratch.fill(None);
+                self.add_thread(&mut clist, self.start, bytes, pos, &mut scratch, &mut stack);
+            }
+            if clist.is_empty() {
+                break;
+            }
+            stats.positions += 1;
+            stats.steps += clist.dense.len();
+            let next = haystack.decode(pos).filter(|&(_, len)| pos + len <= end);
+            for &id in &clist.dense {
+                match &self.states[id] {
+                    State::Class(class, to) => {
+                        if let Some((c, len)) = next {
+                            if class.contains(c) {
+                                scratch.copy_from_slice(clist.slots(id));
+                                let pos = pos + len;
+                                self.add_thread(
+                                    &mut nlist,
+                                    *to,
+                                    bytes,
+                                    pos,
+                                    &mut scratch,
+                                    &mut stack,
+                                );
+                            }
+                        }
+                    }
+                    State::Match(_) => {
+                        slots.copy_from_slice(clist.slots(id));
+                        matched = true;
+                        if earliest {
+                            return true;
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            match next {
+                Some((_, len)) => pos += len,
+                None => break,
+            }
+            core::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        matched
+    }
+
+    /// Adds the threads reachable from `id` without consuming input, with
+    /// `slots` as they are at `id`. `slots` is restored before returning.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        id: usize,
+        haystack: &[u8],
+        pos: usize,
+        slots: &mut [Option<usize>],
+        stack: &mut Vec<Frame>,
+    ) {
+        stack.push(Frame::Explore(id));
+        while let Some(frame) = stack.pop() {
+            let id = match frame {
+                Frame::Explore(id) => id,
+                Frame::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            if !threads.insert(id) {
+                continue;
+            }
+            match self.states[id] {
+                State::Split(a, b) => {
+                    stack.push(Frame::Explore(b));
+                    stack.push(Frame::Explore(a));
+                }
+                State::Epsilon(to) => stack.push(Frame::Explore(to)),
+                State::Save(slot, to) => {
+                    if slot < slots.len() {
+                        stack.push(Frame::Restore(slot, slots[slot]));
+                        slots[slot] = Some(pos);
+                    }
+                    stack.push(Frame::Explore(to));
+                }
+                State::Assert(look, to) => {
+                    if look.is_satisfied(haystack, pos) {
+                        stack.push(Frame::Explore(to));
+                    }
+                }
+                State::Class(..) | State::Match(_) => threads.slots_mut(id).copy_from_slice(slots),
+            }
+        }
+    }
+}
+
+/// How much work a search took.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchStats {
+    /// Positions in the haystack the search looked at.
+    pub positions: usize,
+    /// States the search was in, summed over every position.
+    pub steps: usize,
+}
+
+/// States of an NFA, in the order of their priority.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateSet {
+    ids: Vec<usize>,
+}
+
+impl StateSet {
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.ids.iter().copied()
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Runs an NFA one character at a time, exposing the states it's in after
+/// each, which are those about to consume a character or accepting. Nothing
+/// is pruned once a match is found, unlike in a search.
+///
+/// The states after a step are found as if the input ended there, and found
+/// again once the next character shows which assertions actually hold, so
+/// `\Z` is satisfied before every `\n`.
+#[derive(Clone, Debug)]
+pub struct Simulator<'n> {
+    nfa: &'n Nfa,
+    /// Characters consumed so far.
+    text: String,
+    /// States entered by consuming the last character.
+    entered: Vec<usize>,
+    states: StateSet,
+}
+
+impl<'n> Simulator<'n> {
+    pub fn new(nfa: &'n Nfa) -> Simulator<'n> {
+        let mut simulator = Simulator {
+            nfa,
+            text: String::new(),
+            entered: Vec::new(),
+            states: StateSet::default(),
+        };
+        simulator.states = simulator.closure(0);
+        simulator
+    }
+
+    pub fn nfa(&self) -> &'n Nfa {
+        self.nfa
+    }
+
+    /// States before any character is consumed.
+    pub fn states(&self) -> &StateSet {
+        &self.states
+    }
+
+    /// Consumes `c`, returning the states after it.
+    pub fn step(&mut self, c: char) -> &StateSet {
+        let pos = self.text.len();
+        self.text.push(c);
+        let current = self.closure(pos);
+        self.entered = current
+            .iter()
+            .filter_map(|id| match &self.nfa.states[id] {
+                State::Class(class, to) if class.contains(c) => Some(*to),
+                _ => None,
+            })
+            .collect();
+        self.states = self.closure(self.text.len());
+        &self.states
+    }
+
+    /// Whether the characters consumed so far contain a match ending at the
+    /// last one.
+    pub fn is_match(&self) -> bool {
+        self.states
+            .iter()
+            .any(|id| matches!(self.nfa.states[id], State::Match(_)))
+    }
+
+    /// States reachable from those just entered, and from the start if a match
+    /// may begin at `pos`, without consuming input.
+    fn closure(&self, pos: usize) -> StateSet {
+        let haystack = self.text.as_bytes();
+        let mut seen = vec![false; self.nfa.states.len()];
+        let mut set = StateSet::default();
+        let mut roots = self.entered.clone();
+        if pos == 0 || !self.nfa.anchored {
+            roots.push(self.nfa.start);
+        }
+        for root in roots {
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                if mem::replace(&mut seen[id], true) {
+                    continue;
+                }
+                match self.nfa.states[id] {
+                    State::Split(a, b) => {
+                        stack.push(b);
+                        stack.push(a);
+                    }
+                    State::Epsilon(to) | State::Save(_, to) => stack.push(to),
+                    State::Assert(look, to) => {
+                        if look.is_satisfied(haystack, pos) {
+                            stack.push(to);
+                        }
+                    }
+                    State::Class(..) | State::Match(_) => set.ids.push(id),
+                }
+            }
+        }
+        set
+    }
+}
+
+/// Pending step of `Nfa::add_thread`.
+enum Frame {
+    Explore(usize),
+    /// Puts back the value a slot had before a `Save` overwrote it.
+    Restore(usize, Option<usize>),
+}
+
+/// Sparse set of states preserving insertion order, which encodes priority.
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    /// Slots of each state, `stride` at a time.
+    slots: Vec<Option<usize>>,
+    stride: usize,
+}
+
+impl Threads {
+    fn new(len: usize, stride: usize) -> Threads {
+        Threads {
+            dense: Vec::with_capacity(len),
+            sparse: vec![0; len],
+            slots: vec![None; len * stride],
+            stride,
+        }
+    }
+
+    fn slots(&self, id: usize) -> &[Option<usize>] {
+        &self.slots[id * self.stride..(id + 1) * self.stride]
+    }
+
+    fn slots_mut(&mut self, id: usize) -> &mut [Option<usize>] {
+        &mut self.slots[id * self.stride..(id + 1) * self.stride]
+    }
+
+    fn insert(&mut self, id: usize) -> bool {
+        let i = self.sparse[id];
+        if i < self.dense.len() && self.dense[i] == id {
+            return false;
+        }
+        self.sparse[id] = self.dense.len();
+        self.dense.push(id);
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+/// Matching modes in effect before any inline flags are applied.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    /// Expand `\d`, `\w`, `\s` and the named classes to their Unicode
+    /// variants rather than only ASCII characters.
+    pub unicode: bool,
+    /// Only match at the position the search starts from.
+    pub anchored: bool,
+    /// Maximum estimated size of the compiled states, in bytes.
+    pub size_limit: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            unicode: false,
+            anchored: false,
+            size_limit: 10 << 20,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn apply(&mut self, flags: &ast::Flags) {
+        if let Some(value) = flags.case_insensitive() {
+            self.case_insensitive = value;
+        }
+        if let Some(value) = flags.multi_line() {
+            self.multi_line = value;
+        }
+        if let Some(value) = flags.dot_matches_new_line() {
+            self.dot_matches_new_line = value;
+        }
+        if let Some(value) = flags.unicode() {
+            self.unicode = value;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompileError {
+    /// The states would exceed the size limit, in bytes.
+    TooLarge(usize),
+    /// The pattern uses a construct that can't be matched yet.
+    Unsupported(&'static str),
+    /// `{n,m}` with `n` greater than `m`, which `validate` rejects too.
+    InvalidRange(u8, u8),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::TooLarge(limit) => {
+                write!(
+                    f,
+                    "compiled expression exceeds the size limit of {} bytes",
+                    limit
+                )
+            }
+            CompileError::Unsupported(what) => write!(f, "{} isn't supported", what),
+            CompileError::InvalidRange(n, m) => {
+                write!(f, "invalid repetition range {{{},{}}}", n, m)
+            }
+        }
+    }
+}
+
+impl error::Error for CompileError {}
+
+type Result<T> = core::result::Result<T, CompileError>;
+
+pub fn compile(ast: &ast::Ast) -> Result<Nfa> {
+    compile_with(ast, Config::default())
+}
+
+pub fn compile_with(ast: &ast::Ast, config: Config) -> Result<Nfa> {
+    compile_set(core::slice::from_ref(ast), config)
+}
+
+/// Compiles the patterns into one NFA, where `State::Match(i)` accepts for
+/// the `i`th one. Every pattern numbers its groups from 1 again.
+pub fn compile_set(asts: &[ast::Ast], config: Config) -> Result<Nfa> {
+    let mut compiler = Compiler {
+        states: Vec::new(),
+        size: 0,
+        config,
+        captures: 1,
+        capture_names: vec![None],
+    };
+    let mut starts = Vec::with_capacity(asts.len());
+    for (pattern, ast) in asts.iter().enumerate() {
+        compiler.config = config;
+        compiler.captures = 1;
+        let start = compiler.push(State::Save(0, HOLE))?;
+        let body = ast.accept(&mut compiler)?;
+        compiler.patch(&[Hole::Next(start)], body.start);
+        let end = compiler.push(State::Save(1, HOLE))?;
+        compiler.patch(&body.holes, end);
+        let accept = compiler.push(State::Match(pattern))?;
+        compiler.patch(&[Hole::Next(end)], accept);
+        starts.push(start);
+    }
+    // Without patterns, start from a state that never moves on.
+    let mut start = match starts.pop() {
+        Some(start) => start,
+        None => compiler.push(State::Class(CharClass::new(), 0))?,
+    };
+    while let Some(first) = starts.pop() {
+        start = compiler.push(State::Split(first, start))?;
+    }
+    Ok(Nfa {
+        states: compiler.states,
+        start,
+        anchored: config.anchored,
+        capture_names: compiler.capture_names,
+        patterns: asts.len(),
+    })
+}
+
+const HOLE: usize = usize::MAX;
+
+/// Dangling transition of a partially built fragment.
+#[derive(Copy, Clone, Debug)]
+enum Hole {
+    Next(usize),
+    Right(usize),
+}
+
+struct Fragment {
+    start: usize,
+    holes: Vec<Hole>,
+}
+
+struct Compiler {
+    states: Vec<State>,
+    /// Running total of `Nfa::memory_usage`.
+    size: usize,
+    config: Config,
+    /// Index the next capturing group gets.
+    captures: usize,
+    /// Names of the groups numbered so far.
+    capture_names: Vec<Option<String>>,
+}
+
+impl Compiler {
+    fn push(&mut self, state: State) -> Result<usize> {
+        self.size += mem::size_of::<State>();
+        if let State::Class(class, _) = &state {
+            self.size += mem::size_of_val(class.ranges());
+        }
+        if self.size > self.config.size_limit {
+            return Err(CompileError::TooLarge(self.config.size_limit));
+        }
+        self.states.push(state);
+        Ok(self.states.len() - 1)
+    }
+
+    fn patch(&mut self, holes: &[Hole], to: usize) {
+        for hole in holes {
+            match *hole {
+                Hole::Next(id) => match &mut self.states[id] {
+                    State::Class(_, next)
+                    | State::Epsilon(next)
+                    | State::Save(_, next)
+                    | State::Assert(_, next) => *next = to,
+                    _ => unreachable!(),
+                },
+                Hole::Right(id) => {
+                    if let State::Split(_, b) = &mut self.states[id] {
+                        *b = to;
+                    }
+                }
+            }
+        }
+    }
+
+    fn class(&mut self, class: CharClass) -> Result<Fragment> {
+        let id = self.push(State::Class(class, HOLE))?;
+        Ok(Fragment {
+            start: id,
+            holes: vec![Hole::Next(id)],
+        })
+    }
+
+    fn empty(&mut self) -> Result<Fragment> {
+        let id = self.push(State::Epsilon(HOLE))?;
+        Ok(Fragment {
+            start: id,
+            holes: vec![Hole::Next(id)],
+        })
+    }
+
+    fn concat(&mut self, first: Fragment, second: Fragment) -> Fragment {
+        self.patch(&first.holes, second.start);
+        Fragment {
+            start: first.start,
+            holes: second.holes,
+        }
+    }
+
+    fn optional(&mut self, inner: Fragment) -> Result<Fragment> {
+        let split = self.push(State::Split(inner.start, HOLE))?;
+        let mut holes = inner.holes;
+        holes.push(Hole::Right(split));
+        Ok(Fragment {
+            start: split,
+            holes,
+        })
+    }
+
+    fn star(&mut self, inner: Fragment) -> Result<Fragment> {
+        let split = self.push(State::Split(inner.start, HOLE))?;
+        self.patch(&inner.holes, split);
+        Ok(Fragment {
+            start: split,
+            holes: vec![Hole::Right(split)],
+        })
+    }
+
+    fn plus(&mut self, inner: Fragment) -> Result<Fragment> {
+        let split = self.push(State::Split(inner.start, HOLE))?;
+        self.patch(&inner.holes, split);
+        Ok(Fragment {
+            start: inner.start,
+            holes: vec![Hole::Right(split)],
+        })
+    }
+
+    /// Compiles another copy of a repeated node, with its groups numbered
+    /// from `captures` like in every other copy.
+    fn copy(&mut self, node: &ast::Ast, captures: usize) -> Result<Fragment> {
+        self.captures = captures;
+        self.visit(node)
+    }
+
+    fn repeat(&mut self, node: &ast::Ast, n: u8, captures: usize) -> Result<Fragment> {
+        let mut frag = self.empty()?;
+        for _ in 0..n {
+            let next = self.copy(node, captures)?;
+            frag = self.concat(frag, next);
+        }
+        Ok(frag)
+    }
+}
+
+impl Visitor<Result<Fragment>> for Compiler {
+    fn visit(&mut self, node: &ast::Ast) -> Result<Fragment> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &ast::Literal) -> Result<Fragment> {
+        let mut class = CharClass::from_char(node.value());
+        if self.config.case_insensitive {
+            class.case_fold();
+        }
+        self.class(class)
+    }
+
+    fn visit_literals(&mut self, node: &ast::Literals) -> Result<Fragment> {
+        let mut frag = self.empty()?;
+        for c in node.value().chars() {
+            let next = self.visit_literal(&ast::Literal::new(c))?;
+            frag = self.concat(frag, next);
+        }
+        Ok(frag)
+    }
+
+    fn visit_wildcard(&mut self, _: &ast::Wildcard) -> Result<Fragment> {
+        if self.config.dot_matches_new_line {
+            self.class(CharClass::from_range('\0', char::MAX))
+        } else {
+            self.class(CharClass::wildcard())
+        }
+    }
+
+    fn visit_anchor(&mut self, node: &ast::Anchor) -> Result<Fragment> {
+        use ast::Anchor::*;
+        let look = match node {
+            Start if self.config.multi_line => Look::StartOfLine,
+            End if self.config.multi_line => Look::EndOfLine,
+            Start | StartOfText => Look::StartOfText,
+            End | EndOfText => Look::EndOfText,
+            EndOrBeforeFinalNewline => Look::EndOrBeforeFinalNewline,
+        };
+        let id = self.push(State::Assert(look, HOLE))?;
+        Ok(Fragment {
+            start: id,
+            holes: vec![Hole::Next(id)],
+        })
+    }
+
+    fn visit_perl_class(&mut self, node: &ast::PerlClass) -> Result<Fragment> {
+        // The classes are closed under simple case folding already.
+        self.class(CharClass::from_perl(node, self.config.unicode))
+    }
+
+    fn visit_flags(&mut self, node: &ast::Flags) -> Result<Fragment> {
+        self.config.apply(node);
+        self.empty()
+    }
+
+    fn visit_bracket(&mut self, node: &ast::Bracket) -> Result<Fragment> {
+        if node.has_unicode_property() {
+            return Err(CompileError::Unsupported("Unicode property"));
+        }
+        // Fold before negating, so that `(?i)[^a]` doesn't match `A` either.
+        let mut class = CharClass::from_exprs(node.exprs(), self.config.unicode);
+        if self.config.case_insensitive {
+            class.case_fold();
+        }
+        if node.negated() {
+            class.negate();
+        }
+        self.class(class)
+    }
+
+    fn visit_concatenation(&mut self, node: &ast::Concatenation) -> Result<Fragment> {
+        let mut frag = self.empty()?;
+        for node in node.items() {
+            let next = self.visit(node)?;
+            frag = self.concat(frag, next);
+        }
+        Ok(frag)
+    }
+
+    fn visit_alternative(&mut self, node: &ast::Alternative) -> Result<Fragment> {
+        let mut frags = Vec::with_capacity(node.items().len());
+        for node in node.items() {
+            frags.push(self.visit(node)?);
+        }
+        let mut frag = frags.pop().unwrap();
+        while let Some(first) = frags.pop() {
+            let split = self.push(State::Split(first.start, frag.start))?;
+            let mut holes = first.holes;
+            holes.extend(frag.holes);
+            frag = Fragment {
+                start: split,
+                holes,
+            };
+        }
+        Ok(frag)
+    }
+
+    fn visit_group(&mut self, node: &ast::Group) -> Result<Fragment> {
+        // Flags set inside a group don't leak out of it.
+        let config = self.config;
+        let index = match node.kind() {
+            ast::GroupKind::NonCapturing(flags) => {
+                self.config.apply(flags);
+                None
+            }
+            kind => {
+                // Later copies of a repeated group only reuse its index.
+                if self.captures == self.capture_names.len() {
+                    self.capture_names.push(match kind {
+                        ast::GroupKind::Named(name) => Some(name.clone()),
+                        _ => None,
+                    });
+                }
+                self.captures += 1;
+                Some(self.captures - 1)
+            }
+        };
+        let frag = self.visit(node.inner());
+        self.config = config;
+        match index {
+            Some(index) => {
+                let open = self.push(State::Save(2 * index, HOLE))?;
+                let open = Fragment {
+                    start: open,
+                    holes: vec![Hole::Next(open)],
+                };
+                let frag = self.concat(open, frag?);
+                let close = self.push(State::Save(2 * index + 1, HOLE))?;
+                self.patch(&frag.holes, close);
+                Ok(Fragment {
+                    start: frag.start,
+                    holes: vec![Hole::Next(close)],
+                })
+            }
+            None => frag,
+        }
+    }
+
+    fn visit_repetition(&mut self, node: &ast::Repetition) -> Result<Fragment> {
+        use ast::Quantifier::*;
+        let (inner, captures) = (node.inner(), self.captures);
+        match node.quantifier() {
+            ZeroOrOne => {
+                let inner = self.visit(inner)?;
+                self.optional(inner)
+            }
+            ZeroOrMore => {
+                let inner = self.visit(inner)?;
+                self.star(inner)
+            }
+            OneOrMore => {
+                let inner = self.visit(inner)?;
+                self.plus(inner)
+            }
+            Exact(0) | Range(0, 0) => {
+                // Still number the groups inside, but drop their states.
+                let (len, size) = (self.states.len(), self.size);
+                self.visit(inner)?;
+                self.states.truncate(len);
+                self.size = size;
+                self.empty()
+            }
+            Exact(n) => self.repeat(inner, n, captures),
+            Minimum(n) => {
+                let head = self.repeat(inner, n, captures)?;
+                let inner = self.copy(inner, captures)?;
+                let tail = self.star(inner)?;
+                Ok(self.concat(head, tail))
+            }
+            Range(n, m) if n > m => Err(CompileError::InvalidRange(n, m)),
+            Range(n, m) => {
+                let head = self.repeat(inner, n, captures)?;
+                // Nest the optional copies so that skipping one skips the rest.
+                let mut tail = self.empty()?;
+                for _ in n..m {
+                    let inner = self.copy(inner, captures)?;
+                    let rest = self.concat(inner, tail);
+                    tail = self.optional(rest)?;
+                }
+                Ok(self.concat(head, tail))
+            }
+        }
+    }
+
+    fn visit_look(&mut self, _: &ast::LookAround) -> Result<Fragment> {
+        Err(CompileError::Unsupported("look-around"))
+    }
+
+    fn visit_atomic(&mut self, _: &ast::Ast) -> Result<Fragment> {
+        Err(CompileError::Unsupported("atomic group"))
+    }
+
+    fn visit_backreference(&mut self, _: &ast::Backreference) -> Result<Fragment> {
+        Err(CompileError::Unsupported("backreference"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+
+    #[test]
+    fn simulator_assertions() {
+        let nfa = compile(&parse("a$").unwrap()).unwrap();
+        let mut sim = Simulator::new(&nfa);
+        sim.step('a');
+        assert!(sim.is_match());
+        sim.step('b');
+        assert!(!sim.is_match());
+        let nfa = compile_with(
+            &parse("^a").unwrap(),
+            Config {
+                multi_line: true,
+                ..Config::default()
+            },
+        )
+        .unwrap();
+        let mut sim = Simulator::new(&nfa);
+        assert_eq!(sim.step('\n').len(), 1);
+        assert!(sim.step('b').is_empty());
+    }
+
+    #[test]
+    fn match_ends() {
+        let nfa = compile(&parse("a+|ab").unwrap()).unwrap();
+        assert_eq!(nfa.match_ends("aab", 0), [1, 2]);
+        assert_eq!(nfa.match_ends("aab", 1), [2, 3]);
+        assert!(nfa.match_ends("aab", 2).is_empty());
+    }
+
+    #[test]
+    fn compile_set() {
+        let asts = [
+            parse("ab").unwrap(),
+            parse("b+").unwrap(),
+            parse("^c").unwrap(),
+        ];
+        let nfa = super::compile_set(&asts, Config::default()).unwrap();
+        assert_eq!(nfa.patterns_len(), 3);
+        assert_eq!(nfa.which_match("xabb"), [true, true, false]);
+        assert_eq!(nfa.which_match("cab"), [true, true, true]);
+        assert_eq!(nfa.search("xbab", 0), Some((1, 2)));
+        let empty = super::compile_set(&[], Config::default()).unwrap();
+        assert!(empty.which_match("abc").is_empty());
+        assert_eq!(empty.search("abc", 0), None);
+    }
+
+    fn search(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+        compile(&parse(pattern).unwrap())
+            .unwrap()
+            .search(haystack, 0)
+    }
+
+    #[test]
+    fn memory_usage() {
+        let small = compile(&parse("a").unwrap()).unwrap();
+        let large = compile(&parse("[a-z]{5}|[[:punct:]]").unwrap()).unwrap();
+        assert!(small.memory_usage() >= mem::size_of_val(small.states()));
+        assert!(large.memory_usage() > small.memory_usage());
+    }
+
+    #[test]
+    fn compile_too_large() {
+        let config = Config {
+            size_limit: 100 * mem::size_of::<State>(),
+            ..Config::default()
+        };
+        let ast = parse("(?:ab){60}").unwrap();
+        assert_eq!(
+            compile_with(&ast, config).unwrap_err(),
+            CompileError::TooLarge(config.size_limit)
+        );
+        assert!(compile_with(&parse("(?:ab){20}").unwrap(), config).is_ok());
+    }
+
+    #[test]
+    fn compile_invalid_range() {
+        let ast = parse("a{3,2}").unwrap();
+        assert_eq!(compile(&ast).unwrap_err(), CompileError::InvalidRange(3, 2));
+    }
+
+    #[test]
+    fn captures_len() {
+        let len = |pattern| compile(&parse(pattern).unwrap()).unwrap().captures_len();
+        assert_eq!(len("a"), 1);
+        assert_eq!(len("(a)(?:b)(?P<c>c)"), 3);
+        assert_eq!(len("(a(b)){3}(c)"), 4);
+        assert_eq!(len("(a){0}(b){0,2}(c)"), 4);
+    }
+
+    #[test]
+    fn captures() {
+        let captures = |pattern, haystack| {
+            compile(&parse(pattern).unwrap())
+                .unwrap()
+                .captures(haystack, 0)
+        };
+        assert_eq!(
+            captures("(a)(b(c))", "xabc"),
+            Some(vec![
+                Some(1),
+                Some(4),
+                Some(1),
+                Some(2),
+                Some(2),
+                Some(4),
+                Some(3),
+                Some(4)
+            ])
+        );
+        assert_eq!(
+            captures("(a)|(b)", "b"),
+            Some(vec![Some(0), Some(1), None, None, Some(0), Some(1)])
+        );
+        // The last iteration wins.
+        assert_eq!(
+            captures("(?:(a)|b)+", "abb"),
+            Some(vec![Some(0), Some(3), Some(0), Some(1)])
+        );
+        assert_eq!(
+            captures("(a){2}", "aa"),
+            Some(vec![Some(0), Some(2), Some(1), Some(2)])
+        );
+        assert_eq!(captures("(a)", "b"), None);
+    }
+
+    #[test]
+    fn search_literal() {
+        assert_eq!(search("abc", "xxabcxx"), Some((2, 5)));
+        assert_eq!(search("abc", "xxabxx"), None);
+    }
+
+    #[test]
+    fn search_leftmost_first() {
+        assert_eq!(search("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(search("ab|a", "ab"), Some((0, 2)));
+        assert_eq!(search("a+", "baaab"), Some((1, 4)));
+        assert_eq!(search("a*", "baaab"), Some((0, 0)));
+    }
+
+    #[test]
+    fn search_repetition() {
+        assert_eq!(search("a{2}", "aaa"), Some((0, 2)));
+        assert_eq!(search("a{2,}", "aaaa"), Some((0, 4)));
+        assert_eq!(search("a{1,3}", "aaaa"), Some((0, 3)));
+        assert_eq!(search("ba{0,2}c", "bac"), Some((0, 3)));
+        assert_eq!(search("a{3}", "aa"), None);
+    }
+
+    #[test]
+    fn search_anchor() {
+        assert_eq!(search("^a", "aa"), Some((0, 1)));
+        assert_eq!(search("^b", "ab"), None);
+        assert_eq!(search("a$", "aa"), Some((1, 2)));
+        assert_eq!(search(r"a\z", "a\n"), None);
+        assert_eq!(search(r"\Aa|b", "ab"), Some((0, 1)));
+    }
+
+    #[test]
+    fn search_end_or_before_final_newline() {
+        assert_eq!(search(r"a\Z", "a"), Some((0, 1)));
+        assert_eq!(search(r"a\Z", "a\n"), Some((0, 1)));
+        assert_eq!(search(r"a\Z", "a\nb"), None);
+        assert_eq!(search(r"a\Z", "a\n\n"), None);
+    }
+
+    #[test]
+    fn search_multi_line() {
+        assert_eq!(search("^b", "a\nb"), None);
+        assert_eq!(search("(?m)^b", "a\nb"), Some((2, 3)));
+        assert_eq!(search("(?m)a$", "a\nb"), Some((0, 1)));
+        assert_eq!(search("(?m:a$)|b$", "ab\n"), None);
+        assert_eq!(search(r"(?m)a\z", "a\nb"), None);
+    }
+
+    #[test]
+    fn search_flags_scope() {
+        assert_eq!(search("(?s).", "\n"), Some((0, 1)));
+        assert_eq!(search("(?s:a)(.)", "a\n"), None);
+        assert_eq!(search("((?s).)", "\n"), Some((0, 1)));
+        assert_eq!(search("((?s)a).", "a\n"), None);
+        assert_eq!(search("(?i)ab", "xAB"), Some((1, 3)));
+        assert_eq!(search("a(?i)b", "AB"), None);
+        assert_eq!(search("(?i)a(?-i)b", "Ab AB"), Some((0, 2)));
+    }
+
+    #[test]
+    fn search_unicode() {
+        assert_eq!(search(r"\d", "x٣"), None);
+        assert_eq!(search(r"(?u)\d", "x٣"), Some((1, 3)));
+        assert_eq!(search(r"(?u:\w+)\w", "żółw"), Some((0, 7)));
+        assert_eq!(search(r"(?u)[[:^alpha:]]", "żx1"), Some((3, 4)));
+        assert_eq!(search(r"(?u)a(?-u)\s", "a\u{a0}a "), Some((3, 5)));
+    }
+
+    #[test]
+    fn search_anchored() {
+        let config = Config {
+            anchored: true,
+            ..Config::default()
+        };
+        let nfa = compile_with(&parse("a+").unwrap(), config).unwrap();
+        assert_eq!(nfa.search("aab", 0), Some((0, 2)));
+        assert_eq!(nfa.search("baa", 0), None);
+        assert_eq!(nfa.search("baa", 1), Some((1, 3)));
+    }
+
+    #[test]
+    fn shortest() {
+        let nfa = compile(&parse("a+").unwrap()).unwrap();
+        assert_eq!(nfa.shortest("aaa", 0), Some(1));
+        assert_eq!(nfa.search("aaa", 0), Some((0, 3)));
+        let nfa = compile(&parse("abc|b").unwrap()).unwrap();
+        assert_eq!(nfa.shortest("abc", 0), Some(2));
+        assert_eq!(nfa.shortest("xyz", 0), None);
+    }
+
+    #[test]
+    fn search_within() {
+        let nfa = compile(&parse("b+").unwrap()).unwrap();
+        assert_eq!(nfa.search_within("abbb", 0, 3), Some((1, 3)));
+        assert_eq!(nfa.search_within("aabb", 0, 2), None);
+        let nfa = compile(&parse("a$").unwrap()).unwrap();
+        assert_eq!(nfa.search_within("aa", 0, 1), None);
+    }
+
+    #[test]
+    fn search_bracket() {
+        assert_eq!(search("[[:digit:]]+", "ab123c"), Some((2, 5)));
+        assert_eq!(search("[^a-c]", "abcd"), Some((3, 4)));
+        assert_eq!(search(".", "\nx"), Some((1, 2)));
+        assert_eq!(search(r"\d+", "ab123c"), Some((2, 5)));
+        assert_eq!(search(r"\w+", "  a_1 "), Some((2, 5)));
+        assert_eq!(search(r"\S\s", "ab\tc"), Some((1, 3)));
+        assert_eq!(search(r"\D", "12x"), Some((2, 3)));
+    }
+}
@@ -0,0 +1,137 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use crate::ast::*;
+use crate::visit::{Visitable, Visitor};
+
+/// Renders `ast` as a tree, one node per line, with children indented under
+/// their parent.
+pub fn pretty(ast: &Ast) -> String {
+    ast.accept(&mut Pretty { depth: 0 })
+}
+
+struct Pretty {
+    depth: usize,
+}
+
+impl Pretty {
+    fn line(&self, text: &str) -> String {
+        format!("{:width$}{}\n", "", text, width = 2 * self.depth)
+    }
+
+    /// Line for a node followed by the lines of its children.
+    fn node<'a, I: IntoIterator<Item = &'a Ast>>(&mut self, text: &str, children: I) -> String {
+        let mut out = self.line(text);
+        self.depth += 1;
+        for child in children {
+            out += &self.visit(child);
+        }
+        self.depth -= 1;
+        out
+    }
+}
+
+impl Visitor<String> for Pretty {
+    fn visit(&mut self, node: &Ast) -> String {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &Literal) -> String {
+        self.line(&format!("Literal {:?}", node.value()))
+    }
+
+    fn visit_literals(&mut self, node: &Literals) -> String {
+        self.line(&format!("Literals {:?}", node.value()))
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> String {
+        self.line("Wildcard")
+    }
+
+    fn visit_anchor(&mut self, node: &Anchor) -> String {
+        self.line(&format!("Anchor {:?}", node))
+    }
+
+    fn visit_perl_class(&mut self, node: &PerlClass) -> String {
+        self.line(&format!("PerlClass {}", node))
+    }
+
+    fn visit_flags(&mut self, node: &Flags) -> String {
+        self.line(&format!("Flags {}", node))
+    }
+
+    fn visit_bracket(&mut self, node: &Bracket) -> String {
+        self.line(&format!("Bracket {}", node))
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> String {
+        self.node("Concatenation", node.items())
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> String {
+        self.node("Alternative", node.items())
+    }
+
+    fn visit_group(&mut self, node: &Group) -> String {
+        let text = match node.kind() {
+            GroupKind::Capturing => "Group".to_string(),
+            GroupKind::Named(name) => format!("Group {}", name),
+            GroupKind::NonCapturing(flags) => format!("Group ?{}:", flags),
+        };
+        self.node(&text, Some(node.inner()))
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> String {
+        self.node(
+            &format!("Repetition {}", node.quantifier()),
+            Some(node.inner()),
+        )
+    }
+
+    fn visit_look(&mut self, node: &LookAround) -> String {
+        let kind = match (node.direction(), node.negated()) {
+            (LookDirection::Ahead, false) => "?=",
+            (LookDirection::Ahead, true) => "?!",
+            (LookDirection::Behind, false) => "?<=",
+            (LookDirection::Behind, true) => "?<!",
+        };
+        self.node(&format!("LookAround {}", kind), Some(node.inner()))
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> String {
+        self.node("Atomic", Some(node))
+    }
+
+    fn visit_backreference(&mut self, node: &Backreference) -> String {
+        self.line(&format!("Backreference {}", node.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pretty_alternative() {
+        let out = pretty(&parse("a|b").unwrap());
+        assert_eq!(out, "Alternative\n  Literal 'a'\n  Literal 'b'\n");
+    }
+
+    #[test]
+    fn pretty_nested() {
+        let out = pretty(&parse(r"^(?i:x[a-c]|\d)*.").unwrap());
+        let expected = "\
+Concatenation
+  Anchor Start
+  Repetition *
+    Group ?i:
+      Alternative
+        Concatenation
+          Literal 'x'
+          Bracket [a-c]
+        PerlClass \\d
+  Wildcard
+";
+        assert_eq!(out, expected);
+    }
+}
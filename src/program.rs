@@ -0,0 +1,418 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::ast;
+use crate::class::CharClass;
+use crate::nfa::{CompileError, Config, Look};
+use crate::visit::{Visitable, Visitor};
+
+/// Instruction of a compiled program. Unless it jumps, each one continues
+/// with the instruction after it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inst {
+    /// Consumes the character.
+    Char(char),
+    /// Consumes a single character from the class.
+    Class(CharClass),
+    /// Continues at both addresses, preferring the first one.
+    Split(usize, usize),
+    Jmp(usize),
+    /// Records the current position in a slot.
+    Save(usize),
+    /// Continues only if the position satisfies the assertion.
+    Assert(Look),
+    Match,
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Char(c) => write!(f, "Char {:?}", c),
+            Inst::Class(class) => {
+                write!(f, "Class")?;
+                for &(a, b) in class.ranges() {
+                    if a == b {
+                        write!(f, " {:?}", a)?;
+                    } else {
+                        write!(f, " {:?}-{:?}", a, b)?;
+                    }
+                }
+                Ok(())
+            }
+            Inst::Split(x, y) => write!(f, "Split {}, {}", x, y),
+            Inst::Jmp(x) => write!(f, "Jmp {}", x),
+            Inst::Save(slot) => write!(f, "Save {}", slot),
+            Inst::Assert(look) => write!(f, "Assert {:?}", look),
+            Inst::Match => write!(f, "Match"),
+        }
+    }
+}
+
+/// Lists the instructions one per line, each after its address.
+pub fn dump_program(program: &[Inst]) -> String {
+    let mut output = String::new();
+    for (pc, inst) in program.iter().enumerate() {
+        writeln!(output, "{:04} {}", pc, inst).unwrap();
+    }
+    output
+}
+
+type Result<T> = core::result::Result<T, CompileError>;
+
+/// Compiles the AST to a program that starts at address 0, with slots 0 and 1
+/// around the whole match.
+pub fn compile_program(ast: &ast::Ast) -> Result<Vec<Inst>> {
+    compile_program_with(ast, Config::default())
+}
+
+/// Like `compile_program`, but with the given modes. Neither the anchoring nor
+/// the size limit of `config` is applied.
+pub fn compile_program_with(ast: &ast::Ast, config: Config) -> Result<Vec<Inst>> {
+    let mut compiler = Compiler {
+        program: Vec::new(),
+        config,
+        captures: 1,
+    };
+    compiler.program.push(Inst::Save(0));
+    ast.accept(&mut compiler)?;
+    compiler.program.push(Inst::Save(1));
+    compiler.program.push(Inst::Match);
+    Ok(compiler.program)
+}
+
+/// Address of a jump that is patched once its target is emitted.
+const HOLE: usize = usize::MAX;
+
+struct Compiler {
+    program: Vec<Inst>,
+    config: Config,
+    /// Index the next capturing group gets.
+    captures: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    /// Points the second target of the split at `pc` to the next address.
+    fn patch_split(&mut self, pc: usize) {
+        let next = self.program.len();
+        if let Inst::Split(_, right) = &mut self.program[pc] {
+            *right = next;
+        }
+    }
+
+    fn class(&mut self, class: CharClass) {
+        match class.ranges() {
+            [(a, b)] if a == b => self.push(Inst::Char(*a)),
+            _ => self.push(Inst::Class(class)),
+        };
+    }
+
+    /// Compiles another copy of a repeated node, with its groups numbered
+    /// from `captures` like in every other copy.
+    fn copy(&mut self, node: &ast::Ast, captures: usize) -> Result<()> {
+        self.captures = captures;
+        self.visit(node)
+    }
+
+    fn repeat(&mut self, node: &ast::Ast, n: u8, captures: usize) -> Result<()> {
+        for _ in 0..n {
+            self.copy(node, captures)?;
+        }
+        Ok(())
+    }
+
+    fn star(&mut self, node: &ast::Ast, captures: usize) -> Result<()> {
+        let split = self.push(Inst::Split(self.program.len() + 1, HOLE));
+        self.copy(node, captures)?;
+        self.push(Inst::Jmp(split));
+        self.patch_split(split);
+        Ok(())
+    }
+}
+
+impl Visitor<Result<()>> for Compiler {
+    fn visit(&mut self, node: &ast::Ast) -> Result<()> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, node: &ast::Literal) -> Result<()> {
+        let mut class = CharClass::from_char(node.value());
+        if self.config.case_insensitive {
+            class.case_fold();
+        }
+        self.class(class);
+        Ok(())
+    }
+
+    fn visit_literals(&mut self, node: &ast::Literals) -> Result<()> {
+        for c in node.value().chars() {
+            self.visit_literal(&ast::Literal::new(c))?;
+        }
+        Ok(())
+    }
+
+    fn visit_wildcard(&mut self, _: &ast::Wildcard) -> Result<()> {
+        if self.config.dot_matches_new_line {
+            self.class(CharClass::from_range('\0', char::MAX));
+        } else {
+            self.class(CharClass::wildcard());
+        }
+        Ok(())
+    }
+
+    fn visit_anchor(&mut self, node: &ast::Anchor) -> Result<()> {
+        use ast::Anchor::*;
+        let look = match node {
+            Start if self.config.multi_line => Look::StartOfLine,
+            End if self.config.multi_line => Look::EndOfLine,
+            Start | StartOfText => Look::StartOfText,
+            End | EndOfText => Look::EndOfText,
+            EndOrBeforeFinalNewline => Look::EndOrBeforeFinalNewline,
+        };
+        self.push(Inst::Assert(look));
+        Ok(())
+    }
+
+    fn visit_perl_class(&mut self, node: &ast::PerlClass) -> Result<()> {
+        self.class(CharClass::from_perl(node, self.config.unicode));
+        Ok(())
+    }
+
+    fn visit_flags(&mut self, node: &ast::Flags) -> Result<()> {
+        self.config.apply(node);
+        Ok(())
+    }
+
+    fn visit_bracket(&mut self, node: &ast::Bracket) -> Result<()> {
+        if node.has_unicode_property() {
+            return Err(CompileError::Unsupported("Unicode property"));
+        }
+        let mut class = CharClass::from_exprs(node.exprs(), self.config.unicode);
+        if self.config.case_insensitive {
+            class.case_fold();
+        }
+        if node.negated() {
+            class.negate();
+        }
+        self.class(class);
+        Ok(())
+    }
+
+    fn visit_concatenation(&mut self, node: &ast::Concatenation) -> Result<()> {
+        for node in node.items() {
+            self.visit(node)?;
+        }
+        Ok(())
+    }
+
+    fn visit_alternative(&mut self, node: &ast::Alternative) -> Result<()> {
+        let (last, init) = node.items().split_last().unwrap();
+        let mut jumps = Vec::with_capacity(init.len());
+        for node in init {
+            let split = self.push(Inst::Split(self.program.len() + 1, HOLE));
+            self.visit(node)?;
+            jumps.push(self.push(Inst::Jmp(HOLE)));
+            self.patch_split(split);
+        }
+        self.visit(last)?;
+        let end = self.program.len();
+        for pc in jumps {
+            self.program[pc] = Inst::Jmp(end);
+        }
+        Ok(())
+    }
+
+    fn visit_group(&mut self, node: &ast::Group) -> Result<()> {
+        // Flags set inside a group don't leak out of it.
+        let config = self.config;
+        let index = match node.kind() {
+            ast::GroupKind::NonCapturing(flags) => {
+                self.config.apply(flags);
+                None
+            }
+            _ => {
+                self.captures += 1;
+                Some(self.captures - 1)
+            }
+        };
+        if let Some(index) = index {
+            self.push(Inst::Save(2 * index));
+        }
+        let result = self.visit(node.inner());
+        self.config = config;
+        result?;
+        if let Some(index) = index {
+            self.push(Inst::Save(2 * index + 1));
+        }
+        Ok(())
+    }
+
+    fn visit_repetition(&mut self, node: &ast::Repetition) -> Result<()> {
+        use ast::Quantifier::*;
+        let (inner, captures) = (node.inner(), self.captures);
+        match node.quantifier() {
+            ZeroOrOne => {
+                let split = self.push(Inst::Split(self.program.len() + 1, HOLE));
+                self.visit(inner)?;
+                self.patch_split(split);
+            }
+            ZeroOrMore => self.star(inner, captures)?,
+            OneOrMore => {
+                let start = self.program.len();
+                self.visit(inner)?;
+                self.push(Inst::Split(start, self.program.len() + 1));
+            }
+            Exact(0) | Range(0, 0) => {
+                // Still number the groups inside, but drop the instructions.
+                let len = self.program.len();
+                self.visit(inner)?;
+                self.program.truncate(len);
+            }
+            Exact(n) => self.repeat(inner, n, captures)?,
+            Minimum(n) => {
+                self.repeat(inner, n, captures)?;
+                self.star(inner, captures)?;
+            }
+            Range(n, m) if n > m => return Err(CompileError::InvalidRange(n, m)),
+            Range(n, m) => {
+                self.repeat(inner, n, captures)?;
+                // Skipping one optional copy skips the rest too.
+                let mut splits = Vec::with_capacity(usize::from(m - n));
+                for _ in n..m {
+                    splits.push(self.push(Inst::Split(self.program.len() + 1, HOLE)));
+                    self.copy(inner, captures)?;
+                }
+                for split in splits {
+                    self.patch_split(split);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_look(&mut self, _: &ast::LookAround) -> Result<()> {
+        Err(CompileError::Unsupported("look-around"))
+    }
+
+    fn visit_atomic(&mut self, _: &ast::Ast) -> Result<()> {
+        Err(CompileError::Unsupported("atomic group"))
+    }
+
+    fn visit_backreference(&mut self, _: &ast::Backreference) -> Result<()> {
+        Err(CompileError::Unsupported("backreference"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+    use Inst::*;
+
+    fn compile(pattern: &str) -> Vec<Inst> {
+        compile_program(&parse(pattern).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn alternative() {
+        assert_eq!(
+            compile("a|b"),
+            [
+                Save(0),
+                Split(2, 4),
+                Char('a'),
+                Jmp(5),
+                Char('b'),
+                Save(1),
+                Match
+            ]
+        );
+        assert_eq!(
+            compile("a|b|c"),
+            vec![
+                Save(0),
+                Split(2, 4),
+                Char('a'),
+                Jmp(8),
+                Split(5, 7),
+                Char('b'),
+                Jmp(8),
+                Char('c'),
+                Save(1),
+                Match,
+            ]
+        );
+    }
+
+    #[test]
+    fn star() {
+        assert_eq!(
+            compile("ab*"),
+            [
+                Save(0),
+                Char('a'),
+                Split(3, 5),
+                Char('b'),
+                Jmp(2),
+                Save(1),
+                Match
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_and_counted_repetition() {
+        assert_eq!(
+            compile("(a){1,2}"),
+            vec![
+                Save(0),
+                Save(2),
+                Char('a'),
+                Save(3),
+                Split(5, 8),
+                Save(2),
+                Char('a'),
+                Save(3),
+                Save(1),
+                Match,
+            ]
+        );
+        assert_eq!(compile("(?i)k").len(), 4);
+        assert!(matches!(compile("(?i)k")[1], Class(_)));
+    }
+
+    #[test]
+    fn dump() {
+        let dump = dump_program(&compile("ab"));
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "0000 Save 0",
+                "0001 Char 'a'",
+                "0002 Char 'b'",
+                "0003 Save 1",
+                "0004 Match"
+            ]
+        );
+        let dump = dump_program(&compile("[a-c_]|x*"));
+        assert!(dump.contains("0001 Split 2, 4\n"));
+        assert!(dump.contains("0002 Class '_' 'a'-'c'\n"));
+    }
+
+    #[test]
+    fn unsupported() {
+        let ast = parse("(?=a)").unwrap();
+        assert_eq!(
+            compile_program(&ast),
+            Err(CompileError::Unsupported("look-around"))
+        );
+        let ast = parse("a{3,2}").unwrap();
+        assert_eq!(compile_program(&ast), Err(CompileError::InvalidRange(3, 2)));
+    }
+}
@@ -0,0 +1,1553 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::analysis;
+use crate::ast::{self, ParseError};
+use crate::dfa::LazyDfa;
+use crate::nfa::{self, CompileError, Config, MatchStats, Nfa, Simulator};
+use crate::validate::{self, ValidationError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Parse(ParseError),
+    Invalid(ValidationError),
+    /// The compiled expression would exceed the size limit, in bytes.
+    CompiledTooLarge(usize),
+    /// The pattern parses, but uses a construct that can't be matched yet.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(err) => err.fmt(f),
+            Error::Invalid(err) => err.fmt(f),
+            Error::CompiledTooLarge(limit) => CompileError::TooLarge(*limit).fmt(f),
+            Error::Unsupported(what) => CompileError::Unsupported(what).fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(err) => Some(err),
+            Error::Invalid(err) => Some(err),
+            Error::CompiledTooLarge(_) | Error::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        Error::Parse(err)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Error {
+        Error::Invalid(err)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(err: CompileError) -> Error {
+        match err {
+            CompileError::TooLarge(limit) => Error::CompiledTooLarge(limit),
+            CompileError::Unsupported(what) => Error::Unsupported(what),
+            CompileError::InvalidRange(n, m) => Error::Invalid(ValidationError::InvalidRange(n, m)),
+        }
+    }
+}
+
+/// Number of states the lazy DFA may cache before falling back to the NFA.
+const LAZY_DFA_LIMIT: usize = 1 << 12;
+
+/// Bytes `find_reader` reads at a time, and how far past a match it looks
+/// before reporting it, unless the pattern bounds the match length. Longer
+/// matches may be cut short or missed.
+const READER_WINDOW: usize = 1 << 16;
+
+/// Bytes past the longest possible match that `$` and `\Z` may look at.
+const READER_LOOKAROUND: usize = 8;
+
+/// Number of patterns `Regex::cached` keeps compiled.
+const CACHE_CAPACITY: usize = 64;
+
+/// Patterns compiled by `Regex::cached`, most recently used last.
+static CACHE: OnceLock<Mutex<Vec<Arc<Regex>>>> = OnceLock::new();
+
+/// Compiled regular expression.
+#[derive(Debug)]
+pub struct Regex {
+    pattern: String,
+    nfa: Nfa,
+    /// Literal every match starts with, used to skip ahead to candidates.
+    prefix: String,
+    /// Most bytes a match can span, or `None` if that's unbounded.
+    max_len: Option<usize>,
+    lazy: Mutex<LazyDfa>,
+    /// Shared with every `Captures`, to look groups up by name.
+    capture_names: Arc<[Option<String>]>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    /// Like `new`, but returns a shared copy if `pattern` is one of the
+    /// recently compiled ones.
+    pub fn cached(pattern: &str) -> Result<Arc<Regex>, Error> {
+        let cache = CACHE.get_or_init(|| Mutex::new(Vec::new()));
+        if let Some(regex) = Regex::lookup(cache, pattern) {
+            return Ok(regex);
+        }
+        // Compile without the lock, so other patterns can be looked up.
+        let regex = Arc::new(Regex::new(pattern)?);
+        let mut cache = cache.lock().unwrap();
+        if let Some(cached) = cache.iter().find(|cached| cached.pattern == pattern) {
+            return Ok(Arc::clone(cached));
+        }
+        if cache.len() == CACHE_CAPACITY {
+            cache.remove(0);
+        }
+        cache.push(Arc::clone(&regex));
+        Ok(regex)
+    }
+
+    fn lookup(cache: &Mutex<Vec<Arc<Regex>>>, pattern: &str) -> Option<Arc<Regex>> {
+        let mut cache = cache.lock().unwrap();
+        let at = cache.iter().position(|regex| regex.pattern == pattern)?;
+        let regex = cache.remove(at);
+        cache.push(Arc::clone(&regex));
+        Some(regex)
+    }
+
+    /// Drops the patterns kept by `cached`.
+    pub fn clear_cache() {
+        if let Some(cache) = CACHE.get() {
+            cache.lock().unwrap().clear();
+        }
+    }
+
+    fn from_nfa(pattern: &str, nfa: Nfa, prefix: String, max_len: Option<usize>) -> Regex {
+        Regex {
+            pattern: pattern.to_owned(),
+            prefix,
+            max_len,
+            lazy: Mutex::new(LazyDfa::new(&nfa, LAZY_DFA_LIMIT)),
+            capture_names: nfa.capture_names().into(),
+            nfa,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Number of capturing groups, plus one for the whole match.
+    pub fn captures_len(&self) -> usize {
+        self.nfa.captures_len()
+    }
+
+    /// Name of each capturing group, or `None` if it's unnamed, starting with
+    /// `None` for the whole match.
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.capture_names.iter().map(|name| name.as_deref())
+    }
+
+    /// Steps through the NFA of the expression one character at a time.
+    pub fn simulator(&self) -> Simulator<'_> {
+        Simulator::new(&self.nfa)
+    }
+
+    /// Estimates the number of bytes used by the compiled expression.
+    pub fn memory_usage(&self) -> usize {
+        self.nfa.memory_usage()
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        // The lazy DFA is shared, so only use it if no other thread holds it.
+        if let Ok(mut lazy) = self.lazy.try_lock() {
+            if let Some(matched) = lazy.is_match(&self.nfa, haystack) {
+                return matched;
+            }
+        }
+        self.nfa.search(haystack, 0).is_some()
+    }
+
+    /// Returns the end of the first match to complete in `haystack`, which can
+    /// be sooner than where `find` would end, as in `a+` on `aaa`.
+    pub fn shortest_match(&self, haystack: &str) -> Option<usize> {
+        if let Ok(mut lazy) = self.lazy.try_lock() {
+            if let Some(end) = lazy.shortest_match(&self.nfa, haystack) {
+                return end;
+            }
+        }
+        self.nfa.shortest(haystack, 0)
+    }
+
+    /// Like `is_match`, but ignores matches starting before byte offset
+    /// `start`. The text before it is still seen by assertions like `^`.
+    pub fn is_match_at(&self, haystack: &str, start: usize) -> bool {
+        self.nfa.shortest(haystack, start).is_some()
+    }
+
+    /// Returns the leftmost-first match in `haystack`.
+    pub fn find<'t>(&self, haystack: &'t str) -> Option<Match<'t>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but ignores matches starting before byte offset `start`,
+    /// which must lie on a character boundary. The text before it is still
+    /// seen by assertions like `^`.
+    pub fn find_at<'t>(&self, haystack: &'t str, start: usize) -> Option<Match<'t>> {
+        // No match can start before the first occurrence of the prefix.
+        let start = start + haystack[start..].find(self.prefix.as_str())?;
+        self.nfa
+            .search(haystack, start)
+            .map(|(start, end)| Match::new(haystack, start, end))
+    }
+
+    /// Like `find`, but also reports how much work the search took, to help
+    /// find patterns that are slow to match.
+    pub fn find_with_stats<'t>(&self, haystack: &'t str) -> (Option<Match<'t>>, MatchStats) {
+        let start = match haystack.find(self.prefix.as_str()) {
+            Some(start) => start,
+            None => return (None, MatchStats::default()),
+        };
+        let (found, stats) = self.nfa.search_with_stats(haystack, start);
+        let found = found.map(|(start, end)| Match::new(haystack, start, end));
+        (found, stats)
+    }
+
+    /// Returns the leftmost-first match in `haystack` along with what each
+    /// capturing group matched in it.
+    pub fn captures<'t>(&self, haystack: &'t str) -> Option<Captures<'t>> {
+        let start = haystack.find(self.prefix.as_str())?;
+        self.captures_from(haystack, start)
+    }
+
+    fn captures_from<'t>(&self, haystack: &'t str, start: usize) -> Option<Captures<'t>> {
+        let slots = self.nfa.captures(haystack, start)?;
+        Some(Captures {
+            haystack,
+            slots,
+            names: Arc::clone(&self.capture_names),
+        })
+    }
+
+    /// Like `find`, but only scans the first `max_scan` bytes of `haystack`,
+    /// so matches must end within them.
+    pub fn find_within<'t>(&self, haystack: &'t str, max_scan: usize) -> Option<Match<'t>> {
+        let mut end = max_scan.min(haystack.len());
+        while !haystack.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.nfa
+            .search_within(haystack, 0, end)
+            .map(|(start, end)| Match::new(haystack, start, end))
+    }
+
+    /// Like `is_match`, but over bytes that needn't be valid UTF-8. Each byte
+    /// is read as the character with the same value, U+0000 through U+00FF,
+    /// so pattern characters above U+00FF never match.
+    pub fn is_match_bytes(&self, haystack: &[u8]) -> bool {
+        self.nfa.search(haystack, 0).is_some()
+    }
+
+    /// Like `find`, but over bytes as in `is_match_bytes`. Returns the byte
+    /// range of the match.
+    pub fn find_bytes(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        self.nfa.search(haystack, 0).map(|(start, end)| start..end)
+    }
+
+    /// Returns an iterator over successive non-overlapping matches.
+    pub fn find_iter<'r, 't>(&'r self, haystack: &'t str) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            haystack,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Returns an iterator over the captures of successive non-overlapping
+    /// matches.
+    pub fn captures_iter<'r, 't>(&'r self, haystack: &'t str) -> CaptureMatches<'r, 't> {
+        CaptureMatches {
+            regex: self,
+            haystack,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Returns an iterator over every match, including those overlapping
+    /// others, ordered by where they start and then by length.
+    pub fn find_overlapping<'r, 't>(&'r self, haystack: &'t str) -> OverlappingMatches<'r, 't> {
+        OverlappingMatches {
+            regex: self,
+            haystack,
+            at: 0,
+            start: 0,
+            ends: Vec::new(),
+        }
+    }
+
+    /// Counts the non-overlapping matches `find_iter` would return.
+    pub fn count(&self, haystack: &str) -> usize {
+        self.find_iter(haystack).count()
+    }
+
+    /// Returns an iterator over successive non-overlapping matches in the
+    /// bytes read from `reader`, as byte ranges from the start of the stream.
+    ///
+    /// The stream is matched like `find_bytes`, buffering only a bounded
+    /// window around the current position: as much as the longest possible
+    /// match if the pattern limits it, or else 64 KiB, so that unbounded
+    /// matches longer than that may be cut short or missed.
+    pub fn find_reader<R: Read>(&self, reader: R) -> ReaderMatches<'_, R> {
+        ReaderMatches {
+            regex: self,
+            reader,
+            buffer: Vec::new(),
+            offset: 0,
+            eof: false,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Replaces the leftmost-first match with `replacement`, expanded as in
+    /// `Captures::expand`.
+    pub fn replace(&self, haystack: &str, replacement: &str) -> String {
+        let caps = match self.captures(haystack) {
+            Some(caps) => caps,
+            None => return haystack.to_owned(),
+        };
+        let m = caps.get(0).unwrap();
+        let mut out = haystack[..m.start()].to_owned();
+        caps.expand(replacement, &mut out);
+        out.push_str(&haystack[m.end()..]);
+        out
+    }
+
+    /// Replaces every non-overlapping match with `replacement`, expanded as in
+    /// `Captures::expand`.
+    pub fn replace_all(&self, haystack: &str, replacement: &str) -> String {
+        self.replace_all_with(haystack, |caps| {
+            let mut out = String::new();
+            caps.expand(replacement, &mut out);
+            out
+        })
+    }
+
+    /// Replaces every non-overlapping match with what `replacement` returns
+    /// for its captures.
+    pub fn replace_all_with<F>(&self, haystack: &str, mut replacement: F) -> String
+    where
+        F: FnMut(&Captures) -> String,
+    {
+        let mut out = String::with_capacity(haystack.len());
+        let mut last = 0;
+        for m in self.find_iter(haystack) {
+            // The match is the leftmost-first one from its own start, too.
+            let caps = self.captures_from(haystack, m.start()).unwrap();
+            out.push_str(&haystack[last..m.start()]);
+            out.push_str(&replacement(&caps));
+            last = m.end();
+        }
+        out.push_str(&haystack[last..]);
+        out
+    }
+
+    /// Splits `haystack` by the matches of this expression.
+    pub fn split(&self, haystack: &str) -> Vec<String> {
+        self.split_ranges(haystack)
+            .into_iter()
+            .map(|(start, end)| haystack[start..end].to_owned())
+            .collect()
+    }
+
+    /// Like `split`, but returns the byte ranges of the fields instead of
+    /// copying them out of `haystack`.
+    pub fn split_ranges(&self, haystack: &str) -> Vec<(usize, usize)> {
+        let mut fields = Vec::new();
+        let mut last = 0;
+        for m in self.find_iter(haystack) {
+            fields.push((last, m.start()));
+            last = m.end();
+        }
+        fields.push((last, haystack.len()));
+        fields
+    }
+
+    /// Splits `haystack` into at most `limit` fields, the last of which holds
+    /// the unsplit remainder.
+    pub fn splitn(&self, haystack: &str, limit: usize) -> Vec<String> {
+        let mut fields = Vec::new();
+        if limit == 0 {
+            return fields;
+        }
+        let mut last = 0;
+        for m in self.find_iter(haystack).take(limit - 1) {
+            fields.push(haystack[last..m.start()].to_owned());
+            last = m.end();
+        }
+        fields.push(haystack[last..].to_owned());
+        fields
+    }
+}
+
+/// Configures and compiles a `Regex`.
+///
+/// The options set here are defaults which inline flags in the pattern, like
+/// `(?i)`, can still override.
+#[derive(Clone, Debug)]
+pub struct RegexBuilder {
+    pattern: String,
+    config: Config,
+    nest_limit: usize,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_owned(),
+            config: Config::default(),
+            nest_limit: ast::DEFAULT_NEST_LIMIT,
+        }
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        let ast = ast::parse_with_limit(&self.pattern, self.nest_limit)?;
+        validate::validate(&ast)?;
+        let nfa = nfa::compile_with(&ast, self.config)?;
+        // Case folding and anchoring make the prefix useless for scanning.
+        let prefix = if self.config.case_insensitive || self.config.anchored {
+            String::new()
+        } else {
+            analysis::literal_prefix(&ast)
+        };
+        // Characters take up to 4 bytes in UTF-8.
+        let max_len = analysis::match_length_bounds(&ast)
+            .1
+            .and_then(|len| len.checked_mul(4));
+        Ok(Regex::from_nfa(&self.pattern, nfa, prefix, max_len))
+    }
+
+    /// Same as the `i` flag.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.case_insensitive = yes;
+        self
+    }
+
+    /// Same as the `m` flag, making `^` and `$` match at line boundaries.
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.multi_line = yes;
+        self
+    }
+
+    /// Same as the `s` flag, allowing `.` to match `\n`.
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Same as the `u` flag, making `\d`, `\w`, `\s` and the named classes
+    /// like `[:alpha:]` match the Unicode characters with the property rather
+    /// than only ASCII ones. Unicode mode is off by default.
+    pub fn unicode(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.unicode = yes;
+        self
+    }
+
+    /// Limits the estimated size of the compiled expression, in bytes. The
+    /// default is 10 MiB.
+    pub fn size_limit(&mut self, bytes: usize) -> &mut RegexBuilder {
+        self.config.size_limit = bytes;
+        self
+    }
+
+    /// Limits how deeply groups may nest. The default is 100.
+    pub fn nest_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.nest_limit = limit;
+        self
+    }
+
+    /// Requires matches to begin where the search starts, which is the start
+    /// of the haystack or right after the previous match when iterating.
+    pub fn anchored(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.config.anchored = yes;
+        self
+    }
+}
+
+impl Clone for Regex {
+    fn clone(&self) -> Regex {
+        Regex::from_nfa(
+            &self.pattern,
+            self.nfa.clone(),
+            self.prefix.clone(),
+            self.max_len,
+        )
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Patterns compiled together, to find out which of them match a haystack in
+/// a single pass over it.
+#[derive(Clone, Debug)]
+pub struct RegexSet {
+    patterns: Vec<String>,
+    nfa: Nfa,
+    /// Each pattern on its own, for `matches_par`.
+    #[cfg(feature = "rayon")]
+    members: Vec<Nfa>,
+}
+
+impl RegexSet {
+    pub fn new(patterns: &[&str]) -> Result<RegexSet, Error> {
+        let mut asts = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            let ast = ast::parse(pattern)?;
+            validate::validate(&ast)?;
+            asts.push(ast);
+        }
+        Ok(RegexSet {
+            patterns: patterns.iter().map(|&pattern| pattern.to_owned()).collect(),
+            nfa: nfa::compile_set(&asts, Config::default())?,
+            #[cfg(feature = "rayon")]
+            members: asts.iter().map(nfa::compile).collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.nfa.search(haystack, 0).is_some()
+    }
+
+    /// Returns which of the patterns match somewhere in `haystack`.
+    pub fn matches(&self, haystack: &str) -> SetMatches {
+        SetMatches {
+            matched: self.nfa.which_match(haystack),
+        }
+    }
+
+    /// Like `matches`, but searches for each pattern on its own, on the rayon
+    /// thread pool. This can be faster for large sets.
+    #[cfg(feature = "rayon")]
+    pub fn matches_par(&self, haystack: &str) -> SetMatches {
+        use rayon::prelude::*;
+
+        SetMatches {
+            matched: self
+                .members
+                .par_iter()
+                .map(|nfa| nfa.search(haystack, 0).is_some())
+                .collect(),
+        }
+    }
+}
+
+/// Patterns of a `RegexSet` that matched, by index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    pub fn matched(&self, index: usize) -> bool {
+        self.matched[index]
+    }
+
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    /// Number of patterns in the set, whether they matched or not.
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+
+    /// Returns the indices of the patterns that matched, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter(|&(_, &matched)| matched)
+            .map(|(index, _)| index)
+    }
+}
+
+/// Single match of a regex in a haystack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Match<'t> {
+    haystack: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn new(haystack: &'t str, start: usize, end: usize) -> Match<'t> {
+        Match {
+            haystack,
+            start,
+            end,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+/// What each capturing group matched in a single match, created by
+/// `Regex::captures`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captures<'t> {
+    haystack: &'t str,
+    /// Start and end of each group in turn, starting with the whole match.
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl<'t> Captures<'t> {
+    /// Returns what group `i` matched, where group 0 is the whole match, or
+    /// `None` if it didn't participate in the match.
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        let start = (*self.slots.get(2 * i)?)?;
+        let end = (*self.slots.get(2 * i + 1)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    /// Returns what the group called `name` matched, or `None` if there's no
+    /// such group or it didn't participate in the match.
+    pub fn name(&self, name: &str) -> Option<Match<'t>> {
+        self.get(self.index(name)?)
+    }
+
+    /// Appends `replacement` to `dst`, with each `$group` or `${group}` in it
+    /// replaced by what the group matched, where `group` is either an index
+    /// or a name. Without braces, the reference takes as many letters, digits
+    /// and underscores as there are. Unknown groups expand to nothing, and
+    /// `$$` to `$`.
+    pub fn expand(&self, replacement: &str, dst: &mut String) {
+        let mut rest = replacement;
+        while let Some(at) = rest.find('$') {
+            dst.push_str(&rest[..at]);
+            rest = &rest[at + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                dst.push('$');
+                rest = after;
+                continue;
+            }
+            let (group, after) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+                Some(braced) => braced,
+                None => {
+                    let end = rest
+                        .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+            if group.is_empty() {
+                dst.push('$');
+                continue;
+            }
+            let index = match group.parse::<usize>() {
+                Ok(index) => Some(index),
+                Err(_) => self.index(group),
+            };
+            if let Some(m) = index.and_then(|i| self.get(i)) {
+                dst.push_str(m.as_str());
+            }
+            rest = after;
+        }
+        dst.push_str(rest);
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n.as_deref() == Some(name))
+    }
+}
+
+/// Position of an iterator over non-overlapping matches, which all of them
+/// share so that empty matches are handled the same way.
+#[derive(Clone, Debug, Default)]
+struct Cursor {
+    /// Where the next search starts.
+    at: usize,
+    /// End of the last reported match.
+    last_match: Option<usize>,
+}
+
+impl Cursor {
+    /// Moves past the match from `start` to `end`, returning whether to report
+    /// it. An empty match is stepped over by `width`, the length of the
+    /// character after it, so the next search makes progress, and skipped
+    /// entirely if it abuts the previous match.
+    fn advance(&mut self, start: usize, end: usize, width: usize) -> bool {
+        if start == end {
+            self.at = end + width;
+            if self.last_match == Some(end) {
+                return false;
+            }
+        } else {
+            self.at = end;
+        }
+        self.last_match = Some(end);
+        true
+    }
+
+    /// Moves back by `len`, after as much was dropped from the haystack.
+    fn rebase(&mut self, len: usize) {
+        self.at -= len;
+        self.last_match = self.last_match.and_then(|end| end.checked_sub(len));
+    }
+}
+
+/// Length of the character starting at `pos`, or 1 at the end of `haystack`.
+fn char_width(haystack: &str, pos: usize) -> usize {
+    haystack[pos..].chars().next().map_or(1, char::len_utf8)
+}
+
+/// Iterator over non-overlapping matches, created by `Regex::find_iter`.
+#[derive(Debug)]
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    haystack: &'t str,
+    cursor: Cursor,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            if self.cursor.at > self.haystack.len() {
+                return None;
+            }
+            let m = self.regex.find_at(self.haystack, self.cursor.at)?;
+            let width = char_width(self.haystack, m.end());
+            if self.cursor.advance(m.start(), m.end(), width) {
+                return Some(m);
+            }
+        }
+    }
+}
+
+/// Iterator over all matches, created by `Regex::find_overlapping`.
+#[derive(Debug)]
+pub struct OverlappingMatches<'r, 't> {
+    regex: &'r Regex,
+    haystack: &'t str,
+    /// Where to look for the next start of a match from.
+    at: usize,
+    /// Start of the matches in `ends`.
+    start: usize,
+    /// Ends of the matches yet to be returned, longest first.
+    ends: Vec<usize>,
+}
+
+impl<'r, 't> Iterator for OverlappingMatches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        while self.ends.is_empty() {
+            if self.at > self.haystack.len() {
+                return None;
+            }
+            // No match can start before the next occurrence of the prefix.
+            self.start = self.at + self.haystack[self.at..].find(self.regex.prefix.as_str())?;
+            if self.regex.nfa.is_anchored() && self.start > 0 {
+                return None;
+            }
+            self.ends = self.regex.nfa.match_ends(self.haystack, self.start);
+            self.ends.reverse();
+            self.at = self.start + char_width(self.haystack, self.start);
+        }
+        let end = self.ends.pop().unwrap();
+        Some(Match::new(self.haystack, self.start, end))
+    }
+}
+
+/// Iterator over the captures of non-overlapping matches, created by
+/// `Regex::captures_iter`.
+#[derive(Debug)]
+pub struct CaptureMatches<'r, 't> {
+    regex: &'r Regex,
+    haystack: &'t str,
+    cursor: Cursor,
+}
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        loop {
+            if self.cursor.at > self.haystack.len() {
+                return None;
+            }
+            let m = self.regex.find_at(self.haystack, self.cursor.at)?;
+            let width = char_width(self.haystack, m.end());
+            if self.cursor.advance(m.start(), m.end(), width) {
+                // The match is the leftmost-first one from its own start, too.
+                return self.regex.captures_from(self.haystack, m.start());
+            }
+        }
+    }
+}
+
+/// Iterator over matches in a stream, created by `Regex::find_reader`.
+#[derive(Debug)]
+pub struct ReaderMatches<'r, R> {
+    regex: &'r Regex,
+    reader: R,
+    buffer: Vec<u8>,
+    /// Stream offset of the start of `buffer`.
+    offset: usize,
+    eof: bool,
+    cursor: Cursor,
+}
+
+impl<'r, R: Read> ReaderMatches<'r, R> {
+    /// Whether more input can't change the match from `start` to `end`
+    /// anymore, or the match has grown too long to wait for it to end.
+    fn settled(&self, start: usize, end: usize) -> bool {
+        let len = self.buffer.len();
+        match self.regex.max_len {
+            _ if self.eof => true,
+            Some(max) if max + READER_LOOKAROUND < READER_WINDOW => {
+                start + max + READER_LOOKAROUND <= len
+            }
+            _ => end + READER_WINDOW <= len || start + 2 * READER_WINDOW <= len,
+        }
+    }
+
+    /// Drops the consumed part of the buffer and reads the next chunk.
+    fn fill(&mut self) -> io::Result<()> {
+        // Keep the byte before the search position for `^` and `\A`.
+        let consumed = self.cursor.at.saturating_sub(1);
+        if consumed > 0 {
+            self.buffer.drain(..consumed);
+            self.offset += consumed;
+            self.cursor.rebase(consumed);
+        }
+        // Fill a whole window so short reads don't cause a search per read.
+        let start = self.buffer.len();
+        self.buffer.resize(start + READER_WINDOW, 0);
+        let mut len = start;
+        while len < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(read) => len += read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buffer.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+        self.buffer.truncate(len);
+        Ok(())
+    }
+}
+
+impl<'r, R: Read> Iterator for ReaderMatches<'r, R> {
+    type Item = io::Result<Range<usize>>;
+
+    fn next(&mut self) -> Option<io::Result<Range<usize>>> {
+        loop {
+            if self.cursor.at > self.buffer.len() {
+                return None;
+            }
+            let found = self.regex.nfa.search(&self.buffer[..], self.cursor.at);
+            match found {
+                Some((start, end)) if self.settled(start, end) => {
+                    if self.cursor.advance(start, end, 1) {
+                        return Some(Ok(self.offset + start..self.offset + end));
+                    }
+                    continue;
+                }
+                // More input could extend the match, but nothing before it
+                // is needed anymore.
+                Some((start, _)) => {
+                    if !self.regex.nfa.is_anchored() {
+                        self.cursor.at = start;
+                    }
+                }
+                None if self.eof => return None,
+                None => {
+                    let skip = self.buffer.len().saturating_sub(READER_WINDOW);
+                    if !self.regex.nfa.is_anchored() {
+                        self.cursor.at = self.cursor.at.max(skip);
+                    } else if skip >= self.cursor.at {
+                        return None;
+                    }
+                }
+            }
+            if let Err(err) = self.fill() {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached() {
+        let a = Regex::cached("cache(d|s)?").unwrap();
+        let b = Regex::cached("cache(d|s)?").unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(b.is_match("cached"));
+        assert!(Regex::cached("cache(").is_err());
+        // Fill the cache, evicting everything not used since.
+        for i in 0..CACHE_CAPACITY {
+            Regex::cached(&format!("evict{}", i)).unwrap();
+        }
+        let c = Regex::cached("cache(d|s)?").unwrap();
+        assert!(!Arc::ptr_eq(&a, &c));
+        Regex::clear_cache();
+        assert!(!Arc::ptr_eq(&c, &Regex::cached("cache(d|s)?").unwrap()));
+    }
+
+    #[test]
+    fn regex_set() {
+        let set = RegexSet::new(&["\\d+", "[a-z]+"]).unwrap();
+        let matches = set.matches("abc123");
+        assert!(matches.matched(0) && matches.matched(1));
+        assert_eq!(matches.iter().collect::<Vec<_>>(), [0, 1]);
+        let matches = set.matches("123");
+        assert_eq!(matches.iter().collect::<Vec<_>>(), [0]);
+        assert!(!set.matches("---").matched_any());
+        assert!(set.is_match("x") && !set.is_match(""));
+        let set = RegexSet::new(&["^a$", "(b)c", "(d)"]).unwrap();
+        assert_eq!(set.matches("a").iter().collect::<Vec<_>>(), [0]);
+        assert_eq!(set.matches("xbcd").iter().collect::<Vec<_>>(), [1, 2]);
+        assert!(RegexSet::new(&[]).unwrap().matches("abc").is_empty());
+        assert!(matches!(RegexSet::new(&["a", "("]), Err(Error::Parse(_))));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn matches_par() {
+        let set = RegexSet::new(&[
+            "\\d+", "[a-z]+", "^abc", "c1", "x|y", "(a|b)*z$", "a?", "\\s",
+        ])
+        .unwrap();
+        for haystack in ["abc123", "", "xyz", "\t", "123 abz", "c1"] {
+            assert_eq!(set.matches_par(haystack), set.matches(haystack));
+        }
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let spans = |pattern: &str, haystack: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .find_overlapping(haystack)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spans("aa", "aaa"), [0..2, 1..3]);
+        assert_eq!(spans("a+", "aa"), [0..1, 0..2, 1..2]);
+        assert_eq!(spans("b|bc|abc", "abc"), [0..3, 1..2, 1..3]);
+        assert_eq!(spans("x*", "é"), [0..0, 2..2]);
+        assert!(spans("z", "abc").is_empty());
+        let anchored = RegexBuilder::new("a+").anchored(true).build().unwrap();
+        assert_eq!(anchored.find_overlapping("aab").count(), 2);
+    }
+
+    #[test]
+    fn find_with_stats() {
+        let haystack = "xxxxxxxxxxabc";
+        let (simple, simple_stats) = Regex::new("abc").unwrap().find_with_stats(haystack);
+        let (complex, complex_stats) = Regex::new("(x|xx)*abc").unwrap().find_with_stats(haystack);
+        assert_eq!(simple.unwrap().range(), 10..13);
+        assert_eq!(complex.unwrap().range(), 0..13);
+        assert!(complex_stats.steps > simple_stats.steps);
+        // The search for the prefix skips straight to the match.
+        assert_eq!(simple_stats.positions, 4);
+        let (none, stats) = Regex::new("abd").unwrap().find_with_stats(haystack);
+        assert!(none.is_none());
+        assert_eq!(stats, MatchStats::default());
+    }
+
+    #[test]
+    fn simulator() {
+        use crate::nfa::State;
+
+        let re = Regex::new("ab").unwrap();
+        let mut sim = re.simulator();
+        let states = sim.nfa().states();
+        let find = |wanted: &dyn Fn(&State) -> bool| states.iter().position(wanted).unwrap();
+        let a = find(&|state| matches!(state, State::Class(class, _) if class.contains('a')));
+        let b = find(&|state| matches!(state, State::Class(class, _) if class.contains('b')));
+        let accept = find(&|state| *state == State::Match(0));
+        let ids = |sim: &Simulator| sim.states().iter().collect::<Vec<_>>();
+        assert_eq!(ids(&sim), [a]);
+        assert_eq!(sim.step('a').iter().collect::<Vec<_>>(), [b, a]);
+        assert!(!sim.is_match());
+        assert_eq!(sim.step('b').iter().collect::<Vec<_>>(), [accept, a]);
+        assert!(sim.is_match());
+        sim.step('c');
+        assert_eq!(ids(&sim), [a]);
+    }
+
+    #[test]
+    fn captures() {
+        let re = Regex::new(r"(\w+)@(\w+)(\.com)?").unwrap();
+        let caps = re.captures("mail: joe@example.org").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "joe@example");
+        assert_eq!(caps.get(1).unwrap().as_str(), "joe");
+        assert_eq!(caps.get(2).unwrap().range(), 10..17);
+        assert_eq!(caps.get(3), None);
+        assert_eq!(caps.get(4), None);
+        assert!(re.captures("no mail").is_none());
+    }
+
+    #[test]
+    fn replace_all_with() {
+        let re = Regex::new(r"\w+").unwrap();
+        let upper = re.replace_all_with("hello, big world", |caps| {
+            caps.get(0).unwrap().as_str().to_uppercase()
+        });
+        assert_eq!(upper, "HELLO, BIG WORLD");
+        let re = Regex::new(r"(\w)(\w*)").unwrap();
+        let swapped = re.replace_all_with("ab c", |caps| {
+            format!(
+                "{}{}",
+                caps.get(2).unwrap().as_str(),
+                caps.get(1).unwrap().as_str()
+            )
+        });
+        assert_eq!(swapped, "ba c");
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(re.replace_all_with("ab", |_| "-".to_string()), "-a-b-");
+    }
+
+    #[test]
+    fn captures_name() {
+        let re = Regex::new(r"(?P<k>\w+)=(?P<v>\w+)|(?P<flag>!\w+)").unwrap();
+        let caps = re.captures("set key=value").unwrap();
+        assert_eq!(caps.name("k").unwrap().as_str(), "key");
+        assert_eq!(caps.name("v").unwrap().as_str(), "value");
+        assert_eq!(caps.name("v"), caps.get(2));
+        assert_eq!(caps.name("flag"), None);
+        assert_eq!(caps.name("missing"), None);
+    }
+
+    #[test]
+    fn replace_named() {
+        let re = Regex::new(r"(?P<month>\d{2})/(?P<year>\d{4})").unwrap();
+        let text = "from 01/2024 to 03/2025";
+        assert_eq!(
+            re.replace_all(text, "${year}-${month}"),
+            "from 2024-01 to 2025-03"
+        );
+        assert_eq!(re.replace(text, "$year-$month"), "from 2024-01 to 03/2025");
+        // `$years` refers to a group called `years`, which doesn't exist.
+        assert_eq!(re.replace(text, "$years"), "from  to 03/2025");
+        assert_eq!(re.replace(text, "${year}s"), "from 2024s to 03/2025");
+        assert_eq!(
+            re.replace(text, "$2$1 $0 $$1 ${x} $"),
+            "from 202401 01/2024 $1  $ to 03/2025"
+        );
+        assert_eq!(re.replace("none", "$1"), "none");
+    }
+
+    #[test]
+    fn empty_matches() {
+        let re = Regex::new("a*").unwrap();
+        let spans = |haystack| {
+            re.find_iter(haystack)
+                .map(|m| m.range())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spans("bb"), [0..0, 1..1, 2..2]);
+        assert_eq!(spans("abaa"), [0..1, 2..4]);
+        assert_eq!(spans("é"), [0..0, 2..2]);
+        let captured: Vec<_> = re
+            .captures_iter("abaa")
+            .map(|caps| caps.get(0).unwrap().range())
+            .collect();
+        assert_eq!(captured, spans("abaa"));
+        assert_eq!(re.split("bab"), ["", "b", "b", ""]);
+        let streamed: Vec<_> = re.find_reader(&b"abaa"[..]).map(Result::unwrap).collect();
+        assert_eq!(streamed, spans("abaa"));
+    }
+
+    #[test]
+    fn captures_iter() {
+        let re = Regex::new(r"(\w)(\d)?").unwrap();
+        let groups: Vec<_> = re
+            .captures_iter("a1 b")
+            .map(|caps| {
+                (
+                    caps.get(1).unwrap().as_str(),
+                    caps.get(2).map(|m| m.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(groups, [("a", Some("1")), ("b", None)]);
+    }
+
+    #[test]
+    fn captures_len() {
+        assert_eq!(Regex::new("(a)(b(c))").unwrap().captures_len(), 4);
+        assert_eq!(Regex::new("a(?:b)").unwrap().captures_len(), 1);
+    }
+
+    #[test]
+    fn capture_names() {
+        let re = Regex::new(r"(?P<a>x)(y)(?:(?<b>z)|(w))+").unwrap();
+        let names: Vec<_> = re.capture_names().collect();
+        assert_eq!(names, [None, Some("a"), None, Some("b"), None]);
+        assert_eq!(names.len(), re.captures_len());
+    }
+
+    #[test]
+    fn new_rejects_invalid() {
+        assert_eq!(
+            Regex::new("a)").unwrap_err(),
+            Error::Parse(ParseError::UnopenedGroup(1))
+        );
+        assert_eq!(
+            Regex::new("a{2,1}").unwrap_err(),
+            Error::Invalid(ValidationError::InvalidRange(2, 1))
+        );
+        assert_eq!(
+            RegexBuilder::new("((a))")
+                .nest_limit(1)
+                .build()
+                .unwrap_err(),
+            Error::Parse(ParseError::TooDeep)
+        );
+        assert_eq!(
+            Regex::new("a(?=b)").unwrap_err(),
+            Error::Unsupported("look-around")
+        );
+        assert_eq!(
+            Regex::new(r"[a\p{L}]").unwrap_err(),
+            Error::Unsupported("Unicode property")
+        );
+    }
+
+    #[test]
+    fn new_rejects_too_large() {
+        assert_eq!(
+            Regex::new("((a{255}){255}){255}").unwrap_err(),
+            Error::CompiledTooLarge(10 << 20)
+        );
+        assert_eq!(
+            Regex::new("a{256}").unwrap_err(),
+            Error::Parse(ParseError::CountTooLarge(2))
+        );
+        let err = RegexBuilder::new("a{100}")
+            .size_limit(1000)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, Error::CompiledTooLarge(1000));
+        assert!(RegexBuilder::new("a{100}")
+            .size_limit(1 << 16)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn builder_case_insensitive() {
+        let re = RegexBuilder::new("abc")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("ABC"));
+        assert!(re.is_match("xAbC"));
+        assert!(!Regex::new("abc").unwrap().is_match("ABC"));
+    }
+
+    #[test]
+    fn builder_anchored() {
+        let anchored = RegexBuilder::new("abc").anchored(true).build().unwrap();
+        let unanchored = Regex::new("abc").unwrap();
+        assert!(anchored.is_match("abcd"));
+        assert!(!anchored.is_match("xabc"));
+        assert!(unanchored.is_match("xabc"));
+        assert_eq!(anchored.find("abcd").map(|m| m.range()), Some(0..3));
+        assert_eq!(anchored.find("xabc"), None);
+        assert_eq!(unanchored.find("xabc").map(|m| m.range()), Some(1..4));
+        assert_eq!(anchored.count("abcabc abc"), 2);
+        assert_eq!(unanchored.count("abcabc abc"), 3);
+    }
+
+    #[test]
+    fn case_insensitive_literals() {
+        let re = Regex::new("(?i)abc").unwrap();
+        assert!(re.is_match("ABC"));
+        assert!(re.is_match("aBc"));
+        assert!(!re.is_match("ABD"));
+        assert!(Regex::new("(?i)źdźbło").unwrap().is_match("ŹDŹBŁO"));
+    }
+
+    #[test]
+    fn case_insensitive_ranges() {
+        let re = Regex::new("(?i)[a-z]+").unwrap();
+        assert_eq!(re.find("12 HeLLo!").map(|m| m.as_str()), Some("HeLLo"));
+        let re = Regex::new("(?i)[^a-c]").unwrap();
+        assert_eq!(re.find("AbCd").map(|m| m.as_str()), Some("d"));
+        let re = Regex::new("(?i)[[:upper:]]").unwrap();
+        assert!(re.is_match("x"));
+    }
+
+    #[test]
+    fn case_insensitive_mixed() {
+        let re = Regex::new("(?i)hello (?-i)World").unwrap();
+        let found: Vec<&str> = re
+            .find_iter("HELLO World, hello world, HeLlO World")
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(found, vec!["HELLO World", "HeLlO World"]);
+    }
+
+    #[test]
+    fn builder_multi_line() {
+        let re = RegexBuilder::new("^b$").multi_line(true).build().unwrap();
+        assert_eq!(re.find("a\nb\nc").map(|m| m.range()), Some(2..3));
+        assert!(!Regex::new("^b$").unwrap().is_match("a\nb\nc"));
+    }
+
+    #[test]
+    fn builder_dot_matches_new_line() {
+        let re = RegexBuilder::new("a.b")
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("a\nb"));
+        assert!(!Regex::new("a.b").unwrap().is_match("a\nb"));
+    }
+
+    #[test]
+    fn builder_unicode() {
+        let re = RegexBuilder::new(r"^\d+$").unicode(true).build().unwrap();
+        assert!(re.is_match("42"));
+        assert!(re.is_match("٤٢"));
+        assert!(!Regex::new(r"^\d+$").unwrap().is_match("٤٢"));
+        let re = RegexBuilder::new(r"(?-u:\d)\d")
+            .unicode(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("4٢"));
+        assert!(!re.is_match("٤2"));
+        let re = RegexBuilder::new(r"^(?:\d|[[:digit:]])$")
+            .unicode(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("٣"));
+        for numeric in ["½", "²", "Ⅻ", "①"] {
+            assert!(!re.is_match(numeric), "{}", numeric);
+        }
+        let re = RegexBuilder::new(r"^\w+$").unicode(true).build().unwrap();
+        assert!(re.is_match("żółw_٣") && !re.is_match("½"));
+    }
+
+    #[test]
+    fn builder_merges_inline_flags() {
+        let re = RegexBuilder::new("a(?-i)b")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(re.is_match("Ab"));
+        assert!(!re.is_match("AB"));
+        let re = RegexBuilder::new("(?s:.)$")
+            .multi_line(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("\n\n").map(|m| m.range()), Some(0..1));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let trivial = Regex::new("a").unwrap();
+        let larger = Regex::new("(foo|bar)+[[:alnum:]_]{2,8}").unwrap();
+        assert!(trivial.memory_usage() > 0);
+        assert!(larger.memory_usage() > trivial.memory_usage());
+    }
+
+    #[test]
+    fn is_match() {
+        let re = Regex::new("a.{20}").unwrap();
+        assert!(re.is_match("xxaxxxxxxxxxxxxxxxxxxxxxx"));
+        assert!(!re.is_match("xxaxxxxxxxxxxxxxxxxx"));
+        let long: String = (0..2000)
+            .map(|i| if i % 3 == 0 { 'a' } else { 'b' })
+            .collect();
+        assert!(re.is_match(&long));
+        assert!(!re.is_match(&long.replace('a', "")));
+    }
+
+    #[test]
+    fn space_classes() {
+        let horizontal = Regex::new(r"^\h$").unwrap();
+        let vertical = Regex::new(r"^[\v]$").unwrap();
+        for c in [" ", "\t"] {
+            assert!(horizontal.is_match(c) && !vertical.is_match(c), "{:?}", c);
+        }
+        for c in ["\n", "\x0b", "\x0c", "\r"] {
+            assert!(!horizontal.is_match(c) && vertical.is_match(c), "{:?}", c);
+        }
+        assert!(!horizontal.is_match("a") && !vertical.is_match("a"));
+    }
+
+    #[test]
+    fn shortest_match() {
+        let re = Regex::new("a+").unwrap();
+        assert_eq!(re.shortest_match("aaa"), Some(1));
+        assert_eq!(re.find("aaa").map(|m| m.end()), Some(3));
+        assert_eq!(re.shortest_match("bbb"), None);
+        let re = Regex::new("ab*c?").unwrap();
+        assert_eq!(re.shortest_match("xabbc"), Some(2));
+        assert_eq!(re.find("xabbc").map(|m| m.end()), Some(5));
+    }
+
+    #[test]
+    fn find() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let m = re.find("abc 123 def").unwrap();
+        assert_eq!(m.range(), 4..7);
+        assert_eq!(m.as_str(), "123");
+        assert!(re.find("abc").is_none());
+    }
+
+    #[test]
+    fn find_end_or_before_final_newline() {
+        let re = Regex::new(r"a\Z").unwrap();
+        assert_eq!(re.find("a").map(|m| m.range()), Some(0..1));
+        assert_eq!(re.find("a\n").map(|m| m.range()), Some(0..1));
+        assert!(!re.is_match("a\nb"));
+    }
+
+    #[test]
+    fn find_quantified_group() {
+        let re = Regex::new("(a|b)*").unwrap();
+        assert_eq!(re.find("abba").map(|m| m.range()), Some(0..4));
+        let re = Regex::new("(ab)*c").unwrap();
+        assert_eq!(re.find("xababc").map(|m| m.range()), Some(1..6));
+        assert_eq!(re.find("abac").map(|m| m.range()), Some(3..4));
+        let re = Regex::new("a(b|c)+d").unwrap();
+        assert_eq!(re.find("abcbd").map(|m| m.range()), Some(0..5));
+        assert!(!re.is_match("ad"));
+    }
+
+    #[test]
+    fn find_at() {
+        let re = Regex::new("a[0-9]").unwrap();
+        let haystack = "a1 a2";
+        assert_eq!(re.find_at(haystack, 0).map(|m| m.range()), Some(0..2));
+        assert_eq!(re.find_at(haystack, 1).map(|m| m.range()), Some(3..5));
+        assert_eq!(re.find_at(haystack, 4), None);
+        assert!(re.is_match_at(haystack, 3));
+        assert!(!re.is_match_at(haystack, 4));
+        let re = Regex::new("^a").unwrap();
+        assert_eq!(re.find_at("aa", 1), None);
+        assert!(!re.is_match_at("aa", 1));
+        let re = Regex::new("(?m)^b").unwrap();
+        assert_eq!(re.find_at("b\nb", 1).map(|m| m.range()), Some(2..3));
+        assert_eq!(re.find_at("ab", 1), None);
+    }
+
+    #[test]
+    fn find_with_prefix() {
+        let re = Regex::new("abc.*").unwrap();
+        assert_eq!(re.prefix, "abc");
+        assert_eq!(re.find("xxabcd").map(|m| m.range()), Some(2..6));
+        assert_eq!(re.find("xxabx"), None);
+        assert_eq!(re.find_iter("abc\nabc").count(), 2);
+        let re = Regex::new(".*abc").unwrap();
+        assert_eq!(re.prefix, "");
+        assert_eq!(re.find("xxabc").map(|m| m.range()), Some(0..5));
+        let re = Regex::new("^ab").unwrap();
+        assert_eq!(re.find("xab"), None);
+        let re = RegexBuilder::new("ab")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(re.find("xAB").map(|m| m.range()), Some(1..3));
+    }
+
+    #[test]
+    fn find_within() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let haystack = "ab 12 cdefgh 345";
+        assert_eq!(re.find_within(haystack, 8).map(|m| m.as_str()), Some("12"));
+        assert_eq!(re.find_within(haystack, 4).map(|m| m.as_str()), Some("1"));
+        assert!(re.find_within(&haystack[6..], 7).is_none());
+        assert!(re.find_within(haystack, 0).is_none());
+        assert_eq!(re.find_within("ł1", 2), None);
+        assert_eq!(re.find_within("ł1", 100).map(|m| m.range()), Some(2..3));
+    }
+
+    #[test]
+    fn find_bytes() {
+        let re = Regex::new("a[0-9]+").unwrap();
+        let haystack = b"\xff\xfea12\x80";
+        assert!(re.is_match_bytes(haystack));
+        assert_eq!(re.find_bytes(haystack), Some(2..5));
+        assert!(!re.is_match_bytes(b"\xffa\xfe"));
+        let re = Regex::new("^[^a].$").unwrap();
+        assert_eq!(re.find_bytes(b"\xc3\x28"), Some(0..2));
+        let re = Regex::new("(?m)^x").unwrap();
+        assert_eq!(re.find_bytes(b"\xff\nx"), Some(2..3));
+        let re = Regex::new("é|ł").unwrap();
+        assert_eq!(re.find_bytes(b"\xe9"), Some(0..1));
+        assert!(!re.is_match_bytes("ł".as_bytes()));
+    }
+
+    #[test]
+    fn find_iter() {
+        let re = Regex::new("[0-9]+").unwrap();
+        let found: Vec<&str> = re.find_iter("1 22 333").map(|m| m.as_str()).collect();
+        assert_eq!(found, vec!["1", "22", "333"]);
+    }
+
+    #[test]
+    fn find_iter_empty() {
+        let re = Regex::new("a*").unwrap();
+        let found: Vec<_> = re.find_iter("baab").map(|m| m.range()).collect();
+        assert_eq!(found, vec![0..0, 1..3, 4..4]);
+    }
+
+    #[test]
+    fn count() {
+        let re = Regex::new(r"\d+").unwrap();
+        assert_eq!(re.count("a1 b22 c333"), 3);
+        assert_eq!(re.count("abc"), 0);
+        let re = Regex::new("a*").unwrap();
+        assert_eq!(re.count("bb"), 3);
+        assert_eq!(re.count("bb"), re.find_iter("bb").count());
+        assert_eq!(re.count("baab"), 3);
+    }
+
+    /// Reader returning at most `chunk` bytes per read.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl<'a> Read for Chunked<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn find_reader(pattern: &str, data: &[u8], chunk: usize) -> Vec<Range<usize>> {
+        let re = Regex::new(pattern).unwrap();
+        re.find_reader(Chunked { data, chunk })
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn find_reader_across_chunks() {
+        assert_eq!(find_reader("needle", b"haystack needle", 11), vec![9..15]);
+        assert_eq!(find_reader("[0-9]+", b"a123b45", 2), vec![1..4, 5..7]);
+        assert_eq!(find_reader("^b|c$", b"ab\nb\nc", 1), vec![5..6]);
+        assert_eq!(find_reader("(?m)^b", b"ab\nb", 1), vec![3..4]);
+        assert_eq!(find_reader("x*", b"ab", 1), vec![0..0, 1..1, 2..2]);
+    }
+
+    #[test]
+    fn find_reader_past_window() {
+        let mut data = vec![b'.'; 3 * READER_WINDOW];
+        data.extend_from_slice(b"\xffend");
+        let at = data.len() - 3;
+        assert_eq!(find_reader("^e|end$", &data, 1000), vec![at..at + 3]);
+        assert_eq!(find_reader(r"\A\.", &data, 1000), vec![0..1]);
+    }
+
+    #[test]
+    fn find_reader_bounds_buffer() {
+        let len = 8 * READER_WINDOW;
+        let stream = || (&b"-x"[..]).chain(io::repeat(b'a').take(len as u64));
+        let re = Regex::new("x[a-z]*").unwrap();
+        let mut matches = re.find_reader(stream());
+        let m = matches.next().unwrap().unwrap();
+        assert_eq!(m.start, 1);
+        assert!(m.end > 1 + READER_WINDOW && m.end < len, "{:?}", m);
+        assert!(matches.buffer.len() <= 4 * READER_WINDOW);
+        assert!(matches.next().is_none());
+        let re = Regex::new("x[a-z]{0,100}").unwrap();
+        let mut matches = re.find_reader(stream());
+        assert_eq!(matches.next().unwrap().unwrap(), 1..102);
+        assert!(matches.buffer.len() <= READER_WINDOW + 102);
+        assert!(matches.next().is_none());
+    }
+
+    #[test]
+    fn split() {
+        let re = Regex::new(",").unwrap();
+        assert_eq!(re.split("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(re.split(""), vec![""]);
+    }
+
+    #[test]
+    fn split_ranges() {
+        let re = Regex::new("[0-9]").unwrap();
+        let haystack = "a1b2c";
+        let ranges = re.split_ranges(haystack);
+        assert_eq!(ranges, vec![(0, 1), (2, 3), (4, 5)]);
+        let fields: Vec<&str> = ranges.iter().map(|&(a, b)| &haystack[a..b]).collect();
+        assert_eq!(fields, re.split(haystack));
+    }
+
+    #[test]
+    fn splitn() {
+        let re = Regex::new(",").unwrap();
+        assert_eq!(re.splitn("a,b,c,d", 2), vec!["a", "b,c,d"]);
+        assert_eq!(re.splitn("a,b", 5), vec!["a", "b"]);
+        assert_eq!(re.splitn("a,b", 1), vec!["a,b"]);
+        assert!(re.splitn("a,b", 0).is_empty());
+    }
+}
@@ -0,0 +1,560 @@
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ast::join_literals;
+use crate::ast::*;
+use crate::nfa::{CompileError, Config};
+use crate::visit::Fold;
+
+/// Rewrites `ast` into a smaller one that matches the same and captures the
+/// same groups. Parts that can only match the empty string may become an empty
+/// concatenation, which is written as `(?:)`.
+pub fn simplify(ast: Ast) -> Ast {
+    Simplify.fold(ast)
+}
+
+/// Splices concatenations nested in other concatenations into their parents,
+/// leaving a single level.
+pub fn flatten(ast: Ast) -> Ast {
+    Flatten.fold(ast)
+}
+
+/// Joins runs of adjacent literals in concatenations into `Ast::Literals`,
+/// and a concatenation left with a single run into just that run.
+pub fn merge_literals(ast: Ast) -> Ast {
+    MergeLiterals.fold(ast)
+}
+
+/// Rewrites `ast` so that patterns matching the same strings for one of these
+/// reasons compare equal:
+///
+/// - Capturing groups and non-capturing groups without flags are dropped, so
+///   `(a)`, `(?:a)` and `a` are equal. Groups that end the scope of inline
+///   flags in them stay, as non-capturing groups.
+/// - Nested concatenations are flattened and their literals joined into runs,
+///   so the grouping of `(?:ab)c` and `a(?:bc)` doesn't matter.
+/// - Nested alternations are flattened, and their branches sorted and
+///   deduplicated, so `a|b`, `b|a` and `(?:b|a)|a` are equal. Branches that
+///   set inline flags keep the alternation as it is.
+///
+/// What's lost is which groups capture and which branch is preferred, so the
+/// result is meant for comparisons and not for matching.
+pub fn normalize(ast: Ast) -> Ast {
+    Normalize.fold(ast)
+}
+
+/// Rewrites `\d`, `\s` and `\w` and their negations into the equivalent
+/// brackets, like `[[:digit:]]` for `\d`, so later passes only have to
+/// handle brackets.
+pub fn desugar(ast: Ast) -> Ast {
+    Desugar.fold(ast)
+}
+
+/// Rewrites counted repetitions into copies of what they repeat, like `aaa?`
+/// for `a{2,3}`, leaving only `?`, `*` and `+`. Capturing groups are copied
+/// along, so each copy gets an index of its own.
+///
+/// Fails if the result would exceed the default size limit of `nfa::Config`,
+/// or on a descending range like `{3,2}`.
+pub fn expand_repetitions(ast: Ast) -> Result<Ast, CompileError> {
+    expand_repetitions_with(ast, Config::default().size_limit)
+}
+
+/// Like `expand_repetitions`, but with a size limit of `size_limit` bytes.
+pub fn expand_repetitions_with(ast: Ast, size_limit: usize) -> Result<Ast, CompileError> {
+    let size = expanded_len(&ast)?.saturating_mul(core::mem::size_of::<Ast>());
+    if size > size_limit {
+        return Err(CompileError::TooLarge(size_limit));
+    }
+    Ok(ExpandRepetitions.fold(ast))
+}
+
+struct Simplify;
+
+impl Fold for Simplify {
+    fn fold_concatenation(&mut self, node: Concatenation) -> Ast {
+        match fold_flat(self, node) {
+            Ast::Concatenation(concat) if concat.items().len() == 1 => {
+                concat.into_items().pop().unwrap()
+            }
+            concat => concat,
+        }
+    }
+
+    fn fold_repetition(&mut self, node: Repetition) -> Ast {
+        use Quantifier::*;
+        let quantifier = node.quantifier();
+        let inner = self.fold(node.into_inner());
+        match quantifier {
+            // Capturing groups have to stay, even if they never match.
+            Exact(0) | Range(0, 0) if !has_captures(&inner) => {
+                Ast::Concatenation(Concatenation::new(Vec::new()))
+            }
+            Exact(1) | Range(1, 1) => inner,
+            _ => Ast::Repetition(Repetition::new(inner, quantifier)),
+        }
+    }
+
+    fn fold_alternative(&mut self, node: Alternative) -> Ast {
+        let items: Vec<Ast> = node
+            .into_items()
+            .into_iter()
+            .map(|x| self.fold(x))
+            .collect();
+        // `a|b|c` is `[abc]`.
+        let chars: Vec<BracketExpr> = items
+            .iter()
+            .map_while(|item| match item {
+                Ast::Literal(literal) => Some(BracketExpr::Char(literal.value())),
+                _ => None,
+            })
+            .collect();
+        if chars.len() == items.len() {
+            return Ast::Bracket(Bracket::new(chars, false));
+        }
+        Ast::Alternative(Alternative::new(items))
+    }
+
+    fn fold_group(&mut self, node: Group) -> Ast {
+        let (inner, kind) = node.into_parts();
+        let group = match self.fold(inner) {
+            Ast::Group(nested) => match collapse(&kind, nested.kind()) {
+                Some(kind) => Group::with_kind(nested.into_parts().0, kind),
+                None => Group::with_kind(Ast::Group(nested), kind),
+            },
+            inner => Group::with_kind(inner, kind),
+        };
+        Ast::Group(group)
+    }
+}
+
+struct Normalize;
+
+impl Fold for Normalize {
+    fn fold_concatenation(&mut self, node: Concatenation) -> Ast {
+        let mut items = match fold_flat(self, node) {
+            Ast::Concatenation(concat) => join_literals(concat.into_items()),
+            item => return item,
+        };
+        if items.len() == 1 {
+            return items.pop().unwrap();
+        }
+        Ast::Concatenation(Concatenation::new(items))
+    }
+
+    fn fold_alternative(&mut self, node: Alternative) -> Ast {
+        let mut items = Vec::with_capacity(node.items().len());
+        for item in node.into_items() {
+            match self.fold(item) {
+                Ast::Alternative(alt) => items.extend(alt.into_items()),
+                item => items.push(item),
+            }
+        }
+        // Flags set in one branch apply to the following ones too.
+        if !items.iter().any(sets_flags) {
+            let mut keyed: Vec<_> = items.into_iter().map(|x| (x.to_string(), x)).collect();
+            keyed.sort_by(|a, b| a.0.cmp(&b.0));
+            keyed.dedup_by(|a, b| a.1 == b.1);
+            items = keyed.into_iter().map(|(_, x)| x).collect();
+        }
+        if items.len() == 1 {
+            return items.pop().unwrap();
+        }
+        Ast::Alternative(Alternative::new(items))
+    }
+
+    fn fold_group(&mut self, node: Group) -> Ast {
+        let (inner, kind) = node.into_parts();
+        let inner = self.fold(inner);
+        let flags = match kind {
+            GroupKind::NonCapturing(flags) if flags != Flags::default() => flags,
+            _ if sets_flags(&inner) => Flags::default(),
+            _ => return inner,
+        };
+        let group = match inner {
+            Ast::Group(nested) => match collapse(&GroupKind::NonCapturing(flags), nested.kind()) {
+                Some(kind) => Group::with_kind(nested.into_parts().0, kind),
+                None => Group::with_kind(Ast::Group(nested), GroupKind::NonCapturing(flags)),
+            },
+            inner => Group::with_kind(inner, GroupKind::NonCapturing(flags)),
+        };
+        Ast::Group(group)
+    }
+}
+
+struct Desugar;
+
+impl Fold for Desugar {
+    fn fold_perl_class(&mut self, node: PerlClass) -> Ast {
+        let exprs = match node.kind() {
+            PerlClassKind::Digit => vec![BracketExpr::Class(Class::Digit, false)],
+            PerlClassKind::Space => vec![BracketExpr::Class(Class::Space, false)],
+            PerlClassKind::Word => vec![
+                BracketExpr::Class(Class::Alnum, false),
+                BracketExpr::Char('_'),
+            ],
+        };
+        Ast::Bracket(Bracket::new(exprs, node.negated()))
+    }
+}
+
+struct ExpandRepetitions;
+
+impl Fold for ExpandRepetitions {
+    fn fold_repetition(&mut self, node: Repetition) -> Ast {
+        use Quantifier::*;
+        let quantifier = node.quantifier();
+        let inner = self.fold(node.into_inner());
+        let optional = |inner| Ast::Repetition(Repetition::new(inner, ZeroOrOne));
+        match quantifier {
+            ZeroOrOne | ZeroOrMore | OneOrMore => {
+                Ast::Repetition(Repetition::new(inner, quantifier))
+            }
+            Minimum(0) => Ast::Repetition(Repetition::new(inner, ZeroOrMore)),
+            Minimum(1) => Ast::Repetition(Repetition::new(inner, OneOrMore)),
+            Exact(n) => concat(copies(&inner, n)),
+            Minimum(n) => {
+                let mut items = copies(&inner, n);
+                items.push(Ast::Repetition(Repetition::new(inner, ZeroOrMore)));
+                concat(items)
+            }
+            Range(n, m) => {
+                let mut items = copies(&inner, n);
+                // Nest the optional copies so that skipping one skips the rest.
+                let tail = (n..m).fold(None, |tail, _| match tail {
+                    Some(tail) => Some(optional(concat(vec![inner.clone(), tail]))),
+                    None => Some(optional(inner.clone())),
+                });
+                items.extend(tail);
+                concat(items)
+            }
+        }
+    }
+}
+
+struct Flatten;
+
+impl Fold for Flatten {
+    fn fold_concatenation(&mut self, node: Concatenation) -> Ast {
+        fold_flat(self, node)
+    }
+}
+
+struct MergeLiterals;
+
+impl Fold for MergeLiterals {
+    fn fold_concatenation(&mut self, node: Concatenation) -> Ast {
+        let items = node.into_items().into_iter().map(|x| self.fold(x));
+        let mut items = join_literals(items.collect());
+        if items.len() == 1 {
+            if let Ast::Literals(_) = items[0] {
+                return items.pop().unwrap();
+            }
+        }
+        Ast::Concatenation(Concatenation::new(items))
+    }
+}
+
+/// Folds the items of `node`, splicing in those that end up concatenations.
+fn fold_flat<F: Fold>(folder: &mut F, node: Concatenation) -> Ast {
+    let mut items = Vec::with_capacity(node.items().len());
+    for item in node.into_items() {
+        // Folded items are flat already, so one level of splicing is enough.
+        match folder.fold(item) {
+            Ast::Concatenation(concat) => items.extend(concat.into_items()),
+            item => items.push(item),
+        }
+    }
+    Ast::Concatenation(Concatenation::new(items))
+}
+
+fn copies(ast: &Ast, n: u8) -> Vec<Ast> {
+    (0..n).map(|_| ast.clone()).collect()
+}
+
+/// Single item on its own, or the concatenation of the items.
+fn concat(mut items: Vec<Ast>) -> Ast {
+    if items.len() == 1 {
+        return items.pop().unwrap();
+    }
+    Ast::Concatenation(Concatenation::new(items))
+}
+
+/// Upper bound on the number of nodes of `ast` once `ExpandRepetitions` has
+/// been through it, or an error for a range it can't expand.
+fn expanded_len(ast: &Ast) -> Result<usize, CompileError> {
+    use Quantifier::*;
+    let sum = |items: &[Ast]| {
+        items.iter().try_fold(1usize, |sum, item| {
+            Ok(sum.saturating_add(expanded_len(item)?))
+        })
+    };
+    Ok(match ast {
+        Ast::Concatenation(concat) => sum(concat.items())?,
+        Ast::Alternative(alt) => sum(alt.items())?,
+        Ast::Group(group) => expanded_len(group.inner())?.saturating_add(1),
+        Ast::Look(look) => expanded_len(look.inner())?.saturating_add(1),
+        Ast::Atomic(inner) => expanded_len(inner)?.saturating_add(1),
+        Ast::Repetition(rep) => {
+            let inner = expanded_len(rep.inner())?;
+            // Every copy comes with at most a concatenation and an optional.
+            let copies = match rep.quantifier() {
+                ZeroOrOne | ZeroOrMore | OneOrMore => 1,
+                Exact(n) | Minimum(n) => usize::from(n) + 1,
+                Range(n, m) if n > m => return Err(CompileError::InvalidRange(n, m)),
+                Range(_, m) => usize::from(m),
+            };
+            copies
+                .saturating_mul(inner.saturating_add(2))
+                .saturating_add(1)
+        }
+        _ => 1,
+    })
+}
+
+fn has_captures(ast: &Ast) -> bool {
+    match ast {
+        Ast::Concatenation(concat) => concat.items().iter().any(has_captures),
+        Ast::Alternative(alt) => alt.items().iter().any(has_captures),
+        Ast::Group(group) => group.kind().is_capturing() || has_captures(group.inner()),
+        Ast::Repetition(rep) => has_captures(rep.inner()),
+        Ast::Look(look) => has_captures(look.inner()),
+        Ast::Atomic(inner) => has_captures(inner),
+        _ => false,
+    }
+}
+
+/// Whether `ast` sets inline flags that stay in effect after it.
+fn sets_flags(ast: &Ast) -> bool {
+    match ast {
+        Ast::Flags(_) => true,
+        Ast::Concatenation(concat) => concat.items().iter().any(sets_flags),
+        Ast::Alternative(alt) => alt.items().iter().any(sets_flags),
+        Ast::Repetition(rep) => sets_flags(rep.inner()),
+        _ => false,
+    }
+}
+
+/// Kind of a single group equivalent to a group of kind `outer` directly
+/// around one of kind `inner`, if there is one.
+fn collapse(outer: &GroupKind, inner: &GroupKind) -> Option<GroupKind> {
+    use GroupKind::*;
+    match (outer, inner) {
+        (NonCapturing(outer), NonCapturing(inner)) => Some(NonCapturing(merge(*outer, *inner))),
+        (NonCapturing(flags), capturing) | (capturing, NonCapturing(flags)) => {
+            // Flags only apply inside the capturing group, so they need a
+            // group of their own.
+            if *flags == Flags::default() {
+                Some(capturing.clone())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Flags of `outer` overridden by those set or cleared in `inner`.
+fn merge(outer: Flags, inner: Flags) -> Flags {
+    Flags::new(
+        inner.case_insensitive().or(outer.case_insensitive()),
+        inner.multi_line().or(outer.multi_line()),
+        inner
+            .dot_matches_new_line()
+            .or(outer.dot_matches_new_line()),
+        inner.unicode().or(outer.unicode()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simplified(pattern: &str) -> String {
+        simplify(parse(pattern).unwrap()).to_string()
+    }
+
+    #[test]
+    fn desugar_perl_classes() {
+        let desugared = |pattern| desugar(parse(pattern).unwrap());
+        assert_eq!(desugared(r"\d"), parse("[[:digit:]]").unwrap());
+        assert_eq!(desugared(r"\S"), parse("[^[:space:]]").unwrap());
+        assert_eq!(desugared(r"a\w+"), parse("a[[:alnum:]_]+").unwrap());
+        assert_eq!(desugared(r"(\D|x)"), parse("([^[:digit:]]|x)").unwrap());
+    }
+
+    #[test]
+    fn nested_groups() {
+        assert_eq!(simplified("(?:(?:(?:a)))"), "(?:a)");
+        assert_eq!(simplified("(?:(?:(a)))"), "(a)");
+        assert_eq!(simplified("((?:(?:a)))"), "(a)");
+        assert_eq!(simplified("(((a)))"), "(((a)))");
+        assert_eq!(simplified("(?i:(?-i:(?s:a)))"), "(?s-i:a)");
+        assert_eq!(simplified("(?i:(a))"), "(?i:(a))");
+        assert_eq!(simplified("(?:(?P<n>(?:a)))"), "(?P<n>a)");
+        assert_eq!(simplified("(?P<n>(a))"), "(?P<n>(a))");
+        assert_eq!(simplified("((?i:a))"), "((?i:a))");
+        assert_eq!(simplified("x(?:(?:ab|c))*"), "x(?:ab|c)*");
+    }
+
+    #[test]
+    fn single_char_alternatives() {
+        let chars = ['a', 'b', 'c'].iter().map(|&c| BracketExpr::Char(c));
+        assert_eq!(
+            simplify(parse("a|b|c").unwrap()),
+            Ast::Bracket(Bracket::new(chars.collect(), false))
+        );
+        assert_eq!(simplified("x(?:a|b)*"), "x(?:[ab])*");
+        assert_eq!(simplified("a|bc"), "a|bc");
+        assert_eq!(simplified("a|(b)"), "a|(b)");
+        assert_eq!(simplified("a|[bc]"), "a|[bc]");
+    }
+
+    #[test]
+    fn expand_counted_repetitions() {
+        let expanded = |pattern| expand_repetitions(parse(pattern).unwrap()).unwrap();
+        let a = || Ast::Literal(Literal::new('a'));
+        let optional = Ast::Repetition(Repetition::new(a(), Quantifier::ZeroOrOne));
+        assert_eq!(
+            expanded("a{2,3}"),
+            Ast::Concatenation(Concatenation::new(vec![a(), a(), optional]))
+        );
+        assert_eq!(
+            expanded("a{0,0}"),
+            Ast::Concatenation(Concatenation::new(Vec::new()))
+        );
+        assert_eq!(expanded("a{0,0}").to_string(), "(?:)");
+        assert_eq!(expanded("a{1}b{3}").to_string(), "abbb");
+        assert_eq!(expanded("a{2,}").to_string(), "aaa*");
+        assert_eq!(expanded("a{1,}|b{0,}").to_string(), "a+|b*");
+        assert_eq!(expanded("x{1,3}").to_string(), "x(?:xx?)?");
+        assert_eq!(expanded("(?:(a){2})?").to_string(), "(?:(a)(a))?");
+        assert_eq!(expanded("(?:b{2}){2}").to_string(), "(?:bb)(?:bb)");
+        let huge = parse("(?:(?:a{255}){255}){255}").unwrap();
+        assert_eq!(
+            expand_repetitions(huge.clone()),
+            Err(CompileError::TooLarge(Config::default().size_limit))
+        );
+        assert_eq!(
+            expand_repetitions_with(parse("a{20}").unwrap(), 100),
+            Err(CompileError::TooLarge(100))
+        );
+        assert_eq!(
+            expand_repetitions(parse("x(?:a{3,2})*").unwrap()),
+            Err(CompileError::InvalidRange(3, 2))
+        );
+    }
+
+    #[test]
+    fn empty_repetitions() {
+        let empty = Ast::Concatenation(Concatenation::new(Vec::new()));
+        assert_eq!(simplify(parse("a{0}").unwrap()), empty);
+        assert_eq!(simplify(parse("[ab]{0,0}").unwrap()), empty);
+        assert_eq!(simplified("a{0}b"), "b");
+        assert_eq!(simplified("xa{0}y"), "xy");
+        assert_eq!(simplified("(?:ab){0}|c"), "(?:)|c");
+        assert_eq!(
+            parse("(?:)|c").unwrap(),
+            simplify(parse("(?:ab){0}|c").unwrap())
+        );
+        assert_eq!(simplified("(a){0}"), "(a){0}");
+        assert_eq!(simplified("(?:x(a)){0}b"), "(?:x(a)){0}b");
+    }
+
+    #[test]
+    fn single_repetitions() {
+        assert_eq!(simplified("a{1}"), "a");
+        assert_eq!(simplified("a{1,1}b"), "ab");
+        assert_eq!(simplified("(ab){1}"), "(ab)");
+        assert_eq!(simplified("(?:(?:ab){1}){1}c"), "(?:ab)c");
+    }
+
+    #[test]
+    fn merge_literal_runs() {
+        let merged = merge_literals(parse("abc.def").unwrap());
+        let expected = Ast::Concatenation(Concatenation::new(vec![
+            Ast::Literals(Literals::new("abc".to_string())),
+            Ast::Wildcard(Wildcard),
+            Ast::Literals(Literals::new("def".to_string())),
+        ]));
+        assert_eq!(merged, expected);
+        assert_eq!(merge_literals(merged.clone()), merged);
+        assert_eq!(merged.to_string(), "abc.def");
+        let merged = merge_literals(parse("ab").unwrap());
+        assert_eq!(merged, Ast::Literals(Literals::new("ab".to_string())));
+        let merged = merge_literals(parse(r"a.b(c\.d)+").unwrap());
+        assert_eq!(merged.to_string(), r"a.b(c\.d)+");
+        assert!(matches!(&merged, Ast::Concatenation(c) if c.items()[0] == parse("a").unwrap()));
+    }
+
+    #[test]
+    fn merged_literals_match_the_same() {
+        let repeated = Ast::Repetition(Repetition::new(
+            Ast::Literals(Literals::new("ab".to_string())),
+            Quantifier::OneOrMore,
+        ));
+        assert_eq!(repeated.to_string(), "(?:ab)+");
+        for pattern in &["abc.def", "x(?i)ab", "(ab|cd)+e", "(?:ab)+"] {
+            let ast = parse(pattern).unwrap();
+            let nfa = crate::nfa::compile(&ast).unwrap();
+            let merged = crate::nfa::compile(&merge_literals(ast)).unwrap();
+            for haystack in &["abc.def", "xxABcdabcde", "abab", "abcXdefx"] {
+                assert_eq!(merged.search(*haystack, 0), nfa.search(*haystack, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn flatten_concatenations() {
+        let literal = |c| Ast::Literal(Literal::new(c));
+        let concat = |items| Ast::Concatenation(Concatenation::new(items));
+        let nested = concat(vec![
+            literal('a'),
+            concat(vec![literal('b'), concat(vec![literal('c'), literal('d')])]),
+            Ast::Group(Group::new(concat(vec![
+                concat(vec![literal('e')]),
+                literal('f'),
+            ]))),
+        ]);
+        let flat = flatten(nested);
+        let expected = concat(vec![
+            literal('a'),
+            literal('b'),
+            literal('c'),
+            literal('d'),
+            Ast::Group(Group::new(concat(vec![literal('e'), literal('f')]))),
+        ]);
+        assert_eq!(flat, expected);
+        assert_eq!(flatten(flat.clone()), flat);
+        assert_eq!(simplify(flat.clone()), flat);
+    }
+
+    fn normalized(pattern: &str) -> Ast {
+        normalize(parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn normalize_alternation_order() {
+        assert_eq!(normalized("a|b"), normalized("b|a"));
+        assert_eq!(normalized("a|bc|d"), normalized("d|a|bc"));
+        assert_eq!(normalized("(?:b|a)|a"), normalized("a|b"));
+        assert_eq!(normalized("a|a"), normalized("a"));
+        assert_eq!(normalized("x(a|b)*y"), normalized("x(?:b|a)*y"));
+        assert_ne!(normalized("a|b"), normalized("a|c"));
+        assert_ne!(normalized("a(?i)b|c"), normalized("c|a(?i)b"));
+    }
+
+    #[test]
+    fn normalize_groups() {
+        assert_eq!(normalized("(a)"), normalized("a"));
+        assert_eq!(normalized("((?:a))b"), normalized("ab"));
+        assert_eq!(normalized("(?:ab)c"), normalized("a(?:bc)"));
+        assert_eq!(normalized("x(?:(a|b)|c)"), normalized("x(c|b|a)"));
+        assert_eq!(normalized("(ab)+"), normalized("(?:ab)+"));
+        assert_eq!(normalized("(a(?i)b)c"), normalized("(?:a(?i)b)c"));
+        assert_ne!(normalized("(a(?i)b)c"), normalized("a(?i)bc"));
+        assert_ne!(normalized("(?i:a)"), normalized("a"));
+        assert_eq!(normalized("(?i:(a))"), normalized("(?i:a)"));
+    }
+}
@@ -0,0 +1,162 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+
+use crate::ast::*;
+use crate::visit::{Visitable, Visitor};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    /// `{n,m}` with `n` greater than `m`.
+    InvalidRange(u8, u8),
+    /// Anchor with a quantifier, like `^*`.
+    RepeatedAnchor(Anchor),
+    /// Backreference to a group that doesn't exist.
+    UnknownGroup(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::InvalidRange(n, m) => {
+                write!(f, "invalid repetition range {{{},{}}}", n, m)
+            }
+            ValidationError::RepeatedAnchor(anchor) => write!(f, "repeated anchor {}", anchor),
+            ValidationError::UnknownGroup(name) => write!(f, "unknown group {}", name),
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+/// Checks what the grammar allows but doesn't make sense, returning the first
+/// problem found.
+pub fn validate(ast: &Ast) -> Result<(), ValidationError> {
+    let group_names = crate::analysis::group_names(ast);
+    ast.accept(&mut Validator { group_names })
+}
+
+struct Validator<'a> {
+    group_names: Vec<&'a str>,
+}
+
+impl Visitor<Result<(), ValidationError>> for Validator<'_> {
+    fn visit(&mut self, node: &Ast) -> Result<(), ValidationError> {
+        node.accept(self)
+    }
+
+    fn visit_literal(&mut self, _: &Literal) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_literals(&mut self, _: &Literals) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_wildcard(&mut self, _: &Wildcard) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_anchor(&mut self, _: &Anchor) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_perl_class(&mut self, _: &PerlClass) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_flags(&mut self, _: &Flags) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_bracket(&mut self, _: &Bracket) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn visit_concatenation(&mut self, node: &Concatenation) -> Result<(), ValidationError> {
+        node.items().iter().try_for_each(|x| self.visit(x))
+    }
+
+    fn visit_alternative(&mut self, node: &Alternative) -> Result<(), ValidationError> {
+        node.items().iter().try_for_each(|x| self.visit(x))
+    }
+
+    fn visit_group(&mut self, node: &Group) -> Result<(), ValidationError> {
+        self.visit(node.inner())
+    }
+
+    fn visit_repetition(&mut self, node: &Repetition) -> Result<(), ValidationError> {
+        if let Quantifier::Range(n, m) = node.quantifier() {
+            if n > m {
+                return Err(ValidationError::InvalidRange(n, m));
+            }
+        }
+        if let Ast::Anchor(anchor) = node.inner() {
+            return Err(ValidationError::RepeatedAnchor(*anchor));
+        }
+        self.visit(node.inner())
+    }
+
+    fn visit_look(&mut self, node: &LookAround) -> Result<(), ValidationError> {
+        self.visit(node.inner())
+    }
+
+    fn visit_atomic(&mut self, node: &Ast) -> Result<(), ValidationError> {
+        self.visit(node)
+    }
+
+    fn visit_backreference(&mut self, node: &Backreference) -> Result<(), ValidationError> {
+        if !self.group_names.contains(&node.name()) {
+            return Err(ValidationError::UnknownGroup(node.name().to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(pattern: &str) -> Result<(), ValidationError> {
+        validate(&parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn valid() {
+        for pattern in &["a", r"^(?P<x>a{2,3}|[b-d]+)*\d{0,0}$", "(^)*", "a{3,3}"] {
+            assert_eq!(check(pattern), Ok(()));
+        }
+    }
+
+    #[test]
+    fn invalid_range() {
+        assert_eq!(check("a{3,2}"), Err(ValidationError::InvalidRange(3, 2)));
+        assert_eq!(
+            check("x(b|(c){5,1})"),
+            Err(ValidationError::InvalidRange(5, 1))
+        );
+    }
+
+    #[test]
+    fn unknown_group() {
+        assert_eq!(check(r"(?P<x>a)\k<x>"), Ok(()));
+        assert_eq!(check(r"\k'x'(?<x>a)"), Ok(()));
+        assert_eq!(
+            check(r"(?P<x>a)\k<y>"),
+            Err(ValidationError::UnknownGroup("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn repeated_anchor() {
+        assert_eq!(
+            check("a^*"),
+            Err(ValidationError::RepeatedAnchor(Anchor::Start))
+        );
+        assert_eq!(
+            check(r"\z{2}"),
+            Err(ValidationError::RepeatedAnchor(Anchor::EndOfText))
+        );
+    }
+}
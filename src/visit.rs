@@ -1,72 +1,494 @@
+use alloc::boxed::Box;
+
 use crate::ast::*;
 
 pub trait Visitor<T> {
     fn visit(&mut self, node: &Ast) -> T;
     fn visit_literal(&mut self, node: &Literal) -> T;
+    fn visit_literals(&mut self, node: &Literals) -> T;
     fn visit_wildcard(&mut self, node: &Wildcard) -> T;
+    fn visit_anchor(&mut self, node: &Anchor) -> T;
+    fn visit_perl_class(&mut self, node: &PerlClass) -> T;
+    fn visit_flags(&mut self, node: &Flags) -> T;
     fn visit_bracket(&mut self, node: &Bracket) -> T;
     fn visit_concatenation(&mut self, node: &Concatenation) -> T;
     fn visit_alternative(&mut self, node: &Alternative) -> T;
     fn visit_group(&mut self, node: &Group) -> T;
     fn visit_repetition(&mut self, node: &Repetition) -> T;
+    fn visit_look(&mut self, node: &LookAround) -> T;
+    /// Called with the inner node of an atomic group.
+    fn visit_atomic(&mut self, node: &Ast) -> T;
+    fn visit_backreference(&mut self, node: &Backreference) -> T;
+}
+
+/// Like `Visitor`, but with mutable access to the nodes for in-place rewrites.
+pub trait VisitorMut<T> {
+    fn visit(&mut self, node: &mut Ast) -> T;
+    fn visit_literal(&mut self, node: &mut Literal) -> T;
+    fn visit_literals(&mut self, node: &mut Literals) -> T;
+    fn visit_wildcard(&mut self, node: &mut Wildcard) -> T;
+    fn visit_anchor(&mut self, node: &mut Anchor) -> T;
+    fn visit_perl_class(&mut self, node: &mut PerlClass) -> T;
+    fn visit_flags(&mut self, node: &mut Flags) -> T;
+    fn visit_bracket(&mut self, node: &mut Bracket) -> T;
+    fn visit_concatenation(&mut self, node: &mut Concatenation) -> T;
+    fn visit_alternative(&mut self, node: &mut Alternative) -> T;
+    fn visit_group(&mut self, node: &mut Group) -> T;
+    fn visit_repetition(&mut self, node: &mut Repetition) -> T;
+    fn visit_look(&mut self, node: &mut LookAround) -> T;
+    fn visit_atomic(&mut self, node: &mut Ast) -> T;
+    fn visit_backreference(&mut self, node: &mut Backreference) -> T;
+}
+
+/// Rebuilds a tree bottom-up, by default into an identical one. Overriding a
+/// method replaces the node it's called with; call `fold` on its children to
+/// keep the recursion going.
+pub trait Fold {
+    fn fold(&mut self, node: Ast) -> Ast {
+        match node {
+            Ast::Literal(x) => self.fold_literal(x),
+            Ast::Literals(x) => self.fold_literals(x),
+            Ast::Wildcard(x) => self.fold_wildcard(x),
+            Ast::Anchor(x) => self.fold_anchor(x),
+            Ast::PerlClass(x) => self.fold_perl_class(x),
+            Ast::Flags(x) => self.fold_flags(x),
+            Ast::Bracket(x) => self.fold_bracket(x),
+            Ast::Concatenation(x) => self.fold_concatenation(x),
+            Ast::Alternative(x) => self.fold_alternative(x),
+            Ast::Group(x) => self.fold_group(x),
+            Ast::Repetition(x) => self.fold_repetition(x),
+            Ast::Look(x) => self.fold_look(x),
+            Ast::Atomic(x) => self.fold_atomic(*x),
+            Ast::Backreference(x) => self.fold_backreference(x),
+        }
+    }
+
+    fn fold_literal(&mut self, node: Literal) -> Ast {
+        Ast::Literal(node)
+    }
+
+    fn fold_literals(&mut self, node: Literals) -> Ast {
+        Ast::Literals(node)
+    }
+
+    fn fold_wildcard(&mut self, node: Wildcard) -> Ast {
+        Ast::Wildcard(node)
+    }
+
+    fn fold_anchor(&mut self, node: Anchor) -> Ast {
+        Ast::Anchor(node)
+    }
+
+    fn fold_perl_class(&mut self, node: PerlClass) -> Ast {
+        Ast::PerlClass(node)
+    }
+
+    fn fold_flags(&mut self, node: Flags) -> Ast {
+        Ast::Flags(node)
+    }
+
+    fn fold_bracket(&mut self, node: Bracket) -> Ast {
+        Ast::Bracket(node)
+    }
+
+    fn fold_concatenation(&mut self, node: Concatenation) -> Ast {
+        let items = node.into_items().into_iter();
+        Ast::Concatenation(Concatenation::new(items.map(|x| self.fold(x)).collect()))
+    }
+
+    fn fold_alternative(&mut self, node: Alternative) -> Ast {
+        let items = node.into_items().into_iter();
+        Ast::Alternative(Alternative::new(items.map(|x| self.fold(x)).collect()))
+    }
+
+    fn fold_group(&mut self, node: Group) -> Ast {
+        let (inner, kind) = node.into_parts();
+        Ast::Group(Group::with_kind(self.fold(inner), kind))
+    }
+
+    fn fold_repetition(&mut self, node: Repetition) -> Ast {
+        let quantifier = node.quantifier();
+        Ast::Repetition(Repetition::new(self.fold(node.into_inner()), quantifier))
+    }
+
+    fn fold_look(&mut self, node: LookAround) -> Ast {
+        let (direction, negated) = (node.direction(), node.negated());
+        Ast::Look(LookAround::new(
+            self.fold(node.into_inner()),
+            direction,
+            negated,
+        ))
+    }
+
+    fn fold_atomic(&mut self, node: Ast) -> Ast {
+        Ast::Atomic(Box::new(self.fold(node)))
+    }
+
+    fn fold_backreference(&mut self, node: Backreference) -> Ast {
+        Ast::Backreference(node)
+    }
 }
 
 pub trait Visitable {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T;
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T;
 }
 
 impl Visitable for Ast {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         match self {
             Ast::Literal(x) => x.accept(v),
+            Ast::Literals(x) => x.accept(v),
             Ast::Wildcard(x) => x.accept(v),
+            Ast::Anchor(x) => x.accept(v),
+            Ast::PerlClass(x) => x.accept(v),
+            Ast::Flags(x) => x.accept(v),
             Ast::Bracket(x) => x.accept(v),
             Ast::Concatenation(x) => x.accept(v),
             Ast::Alternative(x) => x.accept(v),
             Ast::Group(x) => x.accept(v),
             Ast::Repetition(x) => x.accept(v),
+            Ast::Look(x) => x.accept(v),
+            Ast::Atomic(x) => v.visit_atomic(x),
+            Ast::Backreference(x) => x.accept(v),
         }
     }
 }
 
 impl Visitable for Literal {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_literal(self)
     }
 }
 
+impl Visitable for Literals {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_literals(self)
+    }
+}
+
 impl Visitable for Wildcard {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_wildcard(self)
     }
 }
 
+impl Visitable for Anchor {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_anchor(self)
+    }
+}
+
+impl Visitable for PerlClass {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_perl_class(self)
+    }
+}
+
+impl Visitable for Flags {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_flags(self)
+    }
+}
+
 impl Visitable for Bracket {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_bracket(self)
     }
 }
 
 impl Visitable for Concatenation {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_concatenation(self)
     }
 }
 
 impl Visitable for Alternative {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_alternative(self)
     }
 }
 
 impl Visitable for Group {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_group(self)
     }
 }
 
 impl Visitable for Repetition {
-    fn accept<T>(&self, v: &mut Visitor<T>) -> T {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
         v.visit_repetition(self)
     }
 }
+
+impl Visitable for LookAround {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_look(self)
+    }
+}
+
+impl Visitable for Backreference {
+    fn accept<T, V: Visitor<T> + ?Sized>(&self, v: &mut V) -> T {
+        v.visit_backreference(self)
+    }
+}
+
+pub trait VisitableMut {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T;
+}
+
+impl VisitableMut for Ast {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        match self {
+            Ast::Literal(x) => x.accept_mut(v),
+            Ast::Literals(x) => x.accept_mut(v),
+            Ast::Wildcard(x) => x.accept_mut(v),
+            Ast::Anchor(x) => x.accept_mut(v),
+            Ast::PerlClass(x) => x.accept_mut(v),
+            Ast::Flags(x) => x.accept_mut(v),
+            Ast::Bracket(x) => x.accept_mut(v),
+            Ast::Concatenation(x) => x.accept_mut(v),
+            Ast::Alternative(x) => x.accept_mut(v),
+            Ast::Group(x) => x.accept_mut(v),
+            Ast::Repetition(x) => x.accept_mut(v),
+            Ast::Look(x) => x.accept_mut(v),
+            Ast::Atomic(x) => v.visit_atomic(x),
+            Ast::Backreference(x) => x.accept_mut(v),
+        }
+    }
+}
+
+impl VisitableMut for Literal {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_literal(self)
+    }
+}
+
+impl VisitableMut for Literals {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_literals(self)
+    }
+}
+
+impl VisitableMut for Wildcard {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_wildcard(self)
+    }
+}
+
+impl VisitableMut for Anchor {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_anchor(self)
+    }
+}
+
+impl VisitableMut for PerlClass {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_perl_class(self)
+    }
+}
+
+impl VisitableMut for Flags {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_flags(self)
+    }
+}
+
+impl VisitableMut for Bracket {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_bracket(self)
+    }
+}
+
+impl VisitableMut for Concatenation {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_concatenation(self)
+    }
+}
+
+impl VisitableMut for Alternative {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_alternative(self)
+    }
+}
+
+impl VisitableMut for Group {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_group(self)
+    }
+}
+
+impl VisitableMut for Repetition {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_repetition(self)
+    }
+}
+
+impl VisitableMut for LookAround {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_look(self)
+    }
+}
+
+impl VisitableMut for Backreference {
+    fn accept_mut<T, V: VisitorMut<T> + ?Sized>(&mut self, v: &mut V) -> T {
+        v.visit_backreference(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Identity;
+
+    impl Fold for Identity {}
+
+    struct Uppercase;
+
+    impl Fold for Uppercase {
+        fn fold_literal(&mut self, node: Literal) -> Ast {
+            Ast::Literal(Literal::new(node.value().to_ascii_uppercase()))
+        }
+    }
+
+    struct UppercaseInPlace;
+
+    impl VisitorMut<()> for UppercaseInPlace {
+        fn visit(&mut self, node: &mut Ast) {
+            node.accept_mut(self)
+        }
+
+        fn visit_literal(&mut self, node: &mut Literal) {
+            node.set_value(node.value().to_ascii_uppercase());
+        }
+
+        fn visit_literals(&mut self, node: &mut Literals) {
+            node.set_value(node.value().to_ascii_uppercase());
+        }
+
+        fn visit_wildcard(&mut self, _node: &mut Wildcard) {}
+
+        fn visit_anchor(&mut self, _node: &mut Anchor) {}
+
+        fn visit_perl_class(&mut self, _node: &mut PerlClass) {}
+
+        fn visit_flags(&mut self, _node: &mut Flags) {}
+
+        fn visit_bracket(&mut self, _node: &mut Bracket) {}
+
+        fn visit_concatenation(&mut self, node: &mut Concatenation) {
+            node.items_mut().iter_mut().for_each(|x| self.visit(x));
+        }
+
+        fn visit_alternative(&mut self, node: &mut Alternative) {
+            node.items_mut().iter_mut().for_each(|x| self.visit(x));
+        }
+
+        fn visit_group(&mut self, node: &mut Group) {
+            self.visit(node.inner_mut())
+        }
+
+        fn visit_repetition(&mut self, node: &mut Repetition) {
+            self.visit(node.inner_mut())
+        }
+
+        fn visit_look(&mut self, node: &mut LookAround) {
+            self.visit(node.inner_mut())
+        }
+
+        fn visit_atomic(&mut self, node: &mut Ast) {
+            self.visit(node)
+        }
+
+        fn visit_backreference(&mut self, _node: &mut Backreference) {}
+    }
+
+    struct Collect(String);
+
+    impl Visitor<()> for Collect {
+        fn visit(&mut self, node: &Ast) {
+            node.accept(self)
+        }
+
+        fn visit_literal(&mut self, node: &Literal) {
+            self.0.push(node.value());
+        }
+
+        fn visit_literals(&mut self, node: &Literals) {
+            self.0.push_str(node.value());
+        }
+
+        fn visit_wildcard(&mut self, _node: &Wildcard) {}
+
+        fn visit_anchor(&mut self, _node: &Anchor) {}
+
+        fn visit_perl_class(&mut self, _node: &PerlClass) {}
+
+        fn visit_flags(&mut self, _node: &Flags) {}
+
+        fn visit_bracket(&mut self, _node: &Bracket) {}
+
+        fn visit_concatenation(&mut self, node: &Concatenation) {
+            node.items().iter().for_each(|x| self.visit(x));
+        }
+
+        fn visit_alternative(&mut self, node: &Alternative) {
+            node.items().iter().for_each(|x| self.visit(x));
+        }
+
+        fn visit_group(&mut self, node: &Group) {
+            self.visit(node.inner())
+        }
+
+        fn visit_repetition(&mut self, node: &Repetition) {
+            self.visit(node.inner())
+        }
+
+        fn visit_look(&mut self, node: &LookAround) {
+            self.visit(node.inner())
+        }
+
+        fn visit_atomic(&mut self, node: &Ast) {
+            self.visit(node)
+        }
+
+        fn visit_backreference(&mut self, _node: &Backreference) {}
+    }
+
+    #[test]
+    fn accept_static_and_dynamic() {
+        let ast = parse("a(b|c)*.d").unwrap();
+        let mut visitor = Collect(String::new());
+        ast.accept(&mut visitor);
+        assert_eq!(visitor.0, "abcd");
+        let mut visitor = Collect(String::new());
+        ast.accept(&mut visitor as &mut dyn Visitor<()>);
+        assert_eq!(visitor.0, "abcd");
+    }
+
+    #[test]
+    fn identity_fold() {
+        for pattern in &[
+            "a",
+            r"^(?i:a|b\d)*[^x-z[:digit:]]{2,3}.$",
+            "((a)|b+)?",
+            "(?>a|b)c",
+        ] {
+            let ast = parse(pattern).unwrap();
+            assert_eq!(Identity.fold(ast.clone()), ast);
+        }
+    }
+
+    #[test]
+    fn fold_literals() {
+        let ast = Uppercase.fold(parse("a(b|c)*d").unwrap());
+        assert_eq!(ast, parse("A(B|C)*D").unwrap());
+    }
+
+    #[test]
+    fn visit_mut_literals() {
+        let mut ast = parse("a(b|c)*[d]").unwrap();
+        ast.accept_mut(&mut UppercaseInPlace);
+        assert_eq!(ast, parse("A(B|C)*[d]").unwrap());
+    }
+}
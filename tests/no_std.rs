@@ -0,0 +1,17 @@
+//! Parses with the crate built as `no_std`, which
+//! `cargo test --no-default-features --test no_std` checks.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::ToString;
+
+use regex::ast::{parse, Ast};
+
+#[test]
+fn parse_without_std() {
+    let ast = parse("a(b|c)*").unwrap();
+    assert!(matches!(ast, Ast::Concatenation(_)));
+    assert_eq!(ast.to_string(), "a(b|c)*");
+    assert_eq!(regex::analysis::node_count(&ast), 7);
+}